@@ -1,56 +1,201 @@
-use crate::token::HeadingLevel;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) enum ASTNode {
+use crate::span::Span;
+pub use crate::token::HeadingLevel;
+
+/// Root of a parsed Markdown document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Document {
+    pub children: Vec<Node>,
+    pub definitions: Vec<LinkReferenceDefinition>,
+    pub metadata: Metadata,
+}
+
+/// A `[label]: destination "title"` definition found in the document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LinkReferenceDefinition {
+    pub label: String,
+    pub destination: String,
+    pub title: Option<String>,
+    pub span: Span,
+}
+
+/// Information about the document as a whole.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Metadata {
+    /// Plain text of the first level 1 heading.
+    pub title: Option<String>,
+}
+
+/// An [`ASTNode`] together with its location in the input.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Node {
+    pub kind: ASTNode,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(kind: ASTNode, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// Serialized as `{"type": "paragraph", "value": [...]}`, with the variant name in snake case.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ASTNode {
     // Block
     Heading {
         level: HeadingLevel,
-        children: Vec<ASTNode>,
+        children: Vec<Node>,
     },
-    Paragraph(Vec<ASTNode>),
-    BlockQuote(Vec<ASTNode>),
+    Paragraph(Vec<Node>),
+    BlockQuote(Vec<Node>),
     CodeBlock {
         language: String,
+        /// The whole info string of a fenced code block, `language` being its first word.
+        info: String,
         text: String,
     },
     List {
         list_type: ListType,
+        /// Number of the first item of an ordered list.
+        start: Option<u32>,
+        /// `.` or `)` of an ordered list.
+        delimiter: Option<char>,
+        /// `-`, `+` or `*` of an unordered list.
+        bullet: Option<char>,
+        /// Whether the items are not separated by blank lines, rendering their paragraphs without `<p>`.
+        tight: bool,
         children: Vec<ListItem>,
     },
     HorizontalRule,
+    /// Raw HTML block, output as is.
+    HtmlBlock(String),
 
     // Inline
-    Bold(Vec<ASTNode>),
-    Italic(Vec<ASTNode>),
+    Bold(Vec<Node>),
+    Italic(Vec<Node>),
     Code(String),
     Link {
         href: String,
         title: Option<String>,
-        children: Vec<ASTNode>,
+        children: Vec<Node>,
     },
     Image {
         href: String,
         title: Option<String>,
-        alt: String,
+        /// Image description, whose [`plain_text`] is the `alt` attribute.
+        alt: Vec<Node>,
     },
     Ruby {
-        target: Vec<ASTNode>,
+        target: Vec<Node>,
         text: String,
     },
+    /// Raw HTML tag, comment or declaration within a paragraph, output as is.
+    InlineHtml(String),
+    /// Line break from two or more trailing spaces or a trailing backslash.
+    HardBreak,
+    /// Any other line break within a paragraph.
+    SoftBreak,
 
     // Text
     Text(String),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) enum ListType {
+/// Concatenates the text of `nodes` without markup.
+pub fn plain_text(nodes: &[Node]) -> String {
+    nodes
+        .iter()
+        .map(|node| match &node.kind {
+            ASTNode::Text(text) | ASTNode::Code(text) => text.clone(),
+            ASTNode::Bold(children) | ASTNode::Italic(children) => plain_text(children),
+            ASTNode::Link { children, .. } => plain_text(children),
+            ASTNode::Image { alt, .. } => plain_text(alt),
+            ASTNode::Ruby { target, .. } => plain_text(target),
+            ASTNode::HardBreak | ASTNode::SoftBreak => "\n".to_string(),
+            _ => String::new(),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ListType {
     Ordered,
     Unordered,
+    /// Bullet list with a `[ ]` or `[x]` task marker on any item. Ordered lists stay
+    /// [`ListType::Ordered`]; the marker of each item is in [`ListItem::checked`].
     CheckBox,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) struct ListItem {
-    pub(crate) checked: bool,
-    pub(crate) children: Vec<ASTNode>,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ListItem {
+    /// State of a `[ ]` or `[x]` task marker, `None` when the item has none.
+    pub checked: Option<bool>,
+    pub children: Vec<Node>,
+    pub span: Span,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{ASTNode, Document, HeadingLevel, ListType, Node};
+    use crate::{parse, span::Span};
+
+    #[test]
+    fn serialize_node() {
+        // JSONの形式
+        let node = Node::new(
+            ASTNode::Heading {
+                level: HeadingLevel::H2,
+                children: vec![Node::new(
+                    ASTNode::Text("Hello".to_string()),
+                    Span::new(3, 8),
+                )],
+            },
+            Span::new(0, 8),
+        );
+        assert_eq!(
+            serde_json::to_value(&node).unwrap(),
+            json!({
+                "kind": {
+                    "type": "heading",
+                    "value": {
+                        "level": "h2",
+                        "children": [{
+                            "kind": { "type": "text", "value": "Hello" },
+                            "span": { "start": 3, "end": 8 },
+                        }],
+                    },
+                },
+                "span": { "start": 0, "end": 8 },
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ASTNode::HorizontalRule).unwrap(),
+            json!({ "type": "horizontal_rule" })
+        );
+        assert_eq!(
+            serde_json::to_value(ListType::CheckBox).unwrap(),
+            json!("check_box")
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        // シリアライズしたものをデシリアライズすると元に戻る
+        let input = "# Title\n\n> **Hello**, *World* `code`\n\n```rust\nfn main() {}\n```\n\n1. [link](/url \"title\")\n2. ![image](/img)\n\n- [x] 漢字《かんじ》\n\n---";
+        let document = parse(input);
+        let json = serde_json::to_string(&document).unwrap();
+        assert_eq!(serde_json::from_str::<Document>(&json).unwrap(), document);
+    }
 }
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use entities::ENTITIES;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+static ENTITY_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^&(?:#[xX]([0-9a-fA-F]{1,6})|#([0-9]{1,7})|[a-zA-Z][a-zA-Z0-9]*);").unwrap()
+});
+/// HTML5 named character references. References without the trailing `;` are not valid in
+/// CommonMark.
+static NAMED_ENTITIES: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
+    ENTITIES
+        .iter()
+        .filter(|entity| entity.entity.ends_with(';'))
+        .map(|entity| (entity.entity, entity.characters))
+        .collect()
+});
+
+/// Decodes entity and numeric character references. Unknown entities are left as is.
+pub(crate) fn decode_entities(text: &str) -> String {
+    decode_entities_with_offsets(text)
+        .into_iter()
+        .map(|(_, c)| c)
+        .collect()
+}
+
+/// Decodes references like [`decode_entities`], pairing each character with the offset of the
+/// character or reference it comes from.
+pub(crate) fn decode_entities_with_offsets(text: &str) -> Vec<(usize, char)> {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut offset = 0;
+    while let Some(c) = text[offset..].chars().next() {
+        if c == '&' {
+            if let Some((characters, len)) = decode_entity(&text[offset..]) {
+                decoded.extend(characters.chars().map(|c| (offset, c)));
+                offset += len;
+
+                continue;
+            }
+        }
+
+        decoded.push((offset, c));
+        offset += c.len_utf8();
+    }
+
+    decoded
+}
+
+/// Decodes the reference at the start of `text`.
+///
+/// Returns the characters it represents and the length of the reference.
+pub(crate) fn decode_entity(text: &str) -> Option<(String, usize)> {
+    let caps = ENTITY_REGEX.captures(text)?;
+    let code_point = if let Some(hex) = caps.get(1) {
+        u32::from_str_radix(hex.as_str(), 16).ok()
+    } else if let Some(decimal) = caps.get(2) {
+        decimal.as_str().parse().ok()
+    } else {
+        let characters = NAMED_ENTITIES.get(&caps[0])?;
+
+        return Some((characters.to_string(), caps[0].len()));
+    };
+
+    // 不正なコードポイントはU+FFFDに置き換える
+    let c = code_point
+        .filter(|&c| c != 0)
+        .and_then(char::from_u32)
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+
+    Some((c.to_string(), caps[0].len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_entities, decode_entities_with_offsets};
+
+    #[test]
+    fn decode_named_entities() {
+        // 名前付き文字参照
+        assert_eq!(
+            decode_entities("&nbsp; &amp; &copy; &AElig; &Dcaron; &frac34; &ngE;"),
+            "\u{a0} & © Æ Ď ¾ ≧̸"
+        );
+        assert_eq!(
+            decode_entities("&nbsp &x; &#; &#x; &ThisIsNotDefined; &hi?;"),
+            "&nbsp &x; &#; &#x; &ThisIsNotDefined; &hi?;"
+        );
+    }
+
+    #[test]
+    fn decode_numeric_entities() {
+        // 数値文字参照
+        assert_eq!(decode_entities("&#35; &#1234; &#992; &#0;"), "# Ӓ Ϡ �");
+        assert_eq!(decode_entities("&#12354;&#x3042;&#X3042;"), "あああ");
+        assert_eq!(
+            decode_entities("&#87654321; &#xD800; &#x110000; &#abcdef0;"),
+            "&#87654321; � � &#abcdef0;"
+        );
+    }
+
+    #[test]
+    fn decode_entities_offsets() {
+        // 元の文字列での位置
+        assert_eq!(
+            decode_entities_with_offsets("a&amp;&ngE;&x;"),
+            vec![
+                (0, 'a'),
+                (1, '&'),
+                (6, '≧'),
+                (6, '\u{338}'),
+                (11, '&'),
+                (12, 'x'),
+                (13, ';'),
+            ]
+        );
+    }
+}
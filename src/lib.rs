@@ -1,6 +1,38 @@
-mod ast;
-mod token;
+pub mod ast;
+mod entity;
+mod options;
+mod parser;
+pub mod renderer;
+pub mod span;
+pub mod token;
 mod tokenizer;
 mod util;
 
-pub fn main() {}
+pub use ast::Document;
+pub use options::ParseOptions;
+pub use token::Token;
+
+/// Parses a Markdown document.
+///
+/// ```
+/// use caffe_paser::ast::ASTNode;
+///
+/// let document = caffe_paser::parse("# Hello, World!");
+/// assert!(matches!(document.children[0].kind, ASTNode::Heading { .. }));
+/// assert_eq!(document.metadata.title.as_deref(), Some("Hello, World!"));
+/// ```
+pub fn parse(input: &str) -> Document {
+    parser::parse(input, &ParseOptions::default())
+}
+
+/// Parses a Markdown document with the given extensions enabled.
+pub fn parse_with_options(input: &str, options: &ParseOptions) -> Document {
+    parser::parse(input, options)
+}
+
+/// Returns the inline tokens of every paragraph and heading, in document order.
+///
+/// This is mainly useful for debugging the parser.
+pub fn tokenize(input: &str, options: &ParseOptions) -> Vec<Token> {
+    parser::tokenize_leaf_blocks(input, options)
+}
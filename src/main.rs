@@ -0,0 +1,166 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use caffe_paser::{parse_with_options, renderer::html::render, tokenize, ParseOptions};
+use clap::{Parser, ValueEnum};
+
+/// Converts Markdown to HTML, a JSON AST or a token dump.
+#[derive(Debug, Parser)]
+#[command(name = "caffe", version)]
+struct Args {
+    /// Input file. Reads from stdin if omitted or `-`.
+    input: Option<PathBuf>,
+
+    /// Output file. Writes to stdout if omitted.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Output format.
+    #[arg(short, long, value_enum, default_value_t = Format::Html)]
+    format: Format,
+
+    /// Parse as strict CommonMark. Extensions can be enabled again with their flags.
+    #[arg(long)]
+    strict: bool,
+
+    /// Enable ruby annotations (`｜漢字《かんじ》`).
+    #[arg(long, overrides_with = "no_ruby")]
+    ruby: bool,
+
+    /// Disable ruby annotations.
+    #[arg(long)]
+    no_ruby: bool,
+
+    /// Enable check list items (`- [ ]`, `- [x]`).
+    #[arg(long, overrides_with = "no_check_list")]
+    check_list: bool,
+
+    /// Disable check list items.
+    #[arg(long)]
+    no_check_list: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Html,
+    Json,
+    Tokens,
+}
+
+impl Args {
+    fn options(&self) -> ParseOptions {
+        let mut options = if self.strict {
+            ParseOptions::strict()
+        } else {
+            ParseOptions::default()
+        };
+        options.ruby = flag(self.ruby, self.no_ruby, options.ruby);
+        options.check_list = flag(self.check_list, self.no_check_list, options.check_list);
+
+        options
+    }
+}
+
+fn flag(enable: bool, disable: bool, default: bool) -> bool {
+    match (enable, disable) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => default,
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let input = match &args.input {
+        Some(path) if path.as_os_str() != "-" => fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?,
+        _ => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .context("failed to read stdin")?;
+            input
+        }
+    };
+
+    let output = convert(&input, args.format, &args.options())?;
+    match &args.output {
+        Some(path) => fs::write(path, output)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => io::stdout()
+            .write_all(output.as_bytes())
+            .context("failed to write stdout")?,
+    }
+
+    Ok(())
+}
+
+fn convert(input: &str, format: Format, options: &ParseOptions) -> Result<String> {
+    let output = match format {
+        Format::Html => render(&parse_with_options(input, options)),
+        Format::Json => serde_json::to_string_pretty(&parse_with_options(input, options))? + "\n",
+        Format::Tokens => tokenize(input, options)
+            .iter()
+            .map(|token| {
+                format!(
+                    "{}..{}\t{:?}\t{:?}\n",
+                    token.span.start, token.span.end, token.token_type, token.raw
+                )
+            })
+            .collect(),
+    };
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::{convert, Args, Format};
+    use caffe_paser::ParseOptions;
+
+    #[test]
+    fn verify_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn extension_flags() {
+        // 拡張記法のフラグ
+        let options = |args: &[&str]| Args::parse_from([&["caffe"], args].concat()).options();
+        assert_eq!(options(&[]), ParseOptions::default());
+        assert_eq!(options(&["--strict"]), ParseOptions::strict());
+
+        let mut expected = ParseOptions::strict();
+        expected.ruby = true;
+        assert_eq!(options(&["--strict", "--ruby"]), expected);
+        assert_eq!(options(&["--no-check-list"]), expected);
+        assert_eq!(
+            options(&["--check-list", "--no-check-list", "--no-ruby", "--ruby"]),
+            expected
+        );
+    }
+
+    #[test]
+    fn convert_formats() {
+        // 出力形式
+        let options = ParseOptions::default();
+        assert_eq!(
+            convert("*Hello*", Format::Html, &options).unwrap(),
+            "<p><em>Hello</em></p>\n"
+        );
+        assert_eq!(
+            convert("*Hello*", Format::Tokens, &options).unwrap(),
+            "0..1\tEmphasis(LeftFlanking)\t\"*\"\n1..6\tText\t\"Hello\"\n6..7\tEmphasis(RightFlanking)\t\"*\"\n"
+        );
+        assert!(convert("*Hello*", Format::Json, &options)
+            .unwrap()
+            .contains("\"type\": \"italic\""));
+    }
+}
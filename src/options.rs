@@ -0,0 +1,43 @@
+/// Switches for the syntax extensions on top of CommonMark.
+///
+/// All extensions are enabled by default. Use [`ParseOptions::strict`] to parse plain CommonMark.
+///
+/// ```
+/// use caffe_paser::{ast::ASTNode, ParseOptions};
+///
+/// let mut options = ParseOptions::default();
+/// options.ruby = false;
+///
+/// let document = caffe_paser::parse_with_options("漢字《かんじ》", &options);
+/// let ASTNode::Paragraph(children) = &document.children[0].kind else {
+///     unreachable!()
+/// };
+/// assert_eq!(children[0].kind, ASTNode::Text("漢字《かんじ》".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ParseOptions {
+    /// Ruby annotations written as `｜漢字《かんじ》` or `漢字《かんじ》`.
+    pub ruby: bool,
+    /// Check list items written as `- [ ]` or `- [x]`.
+    pub check_list: bool,
+}
+
+impl ParseOptions {
+    /// Options with every extension disabled.
+    pub fn strict() -> Self {
+        Self {
+            ruby: false,
+            check_list: false,
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            ruby: true,
+            check_list: true,
+        }
+    }
+}
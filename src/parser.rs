@@ -0,0 +1,871 @@
+mod block;
+mod inline;
+
+use crate::{
+    ast::{plain_text, ASTNode, Document, ListItem, ListType, Metadata, Node},
+    token::{HeadingLevel, Token, TokenType},
+    tokenizer::tokenize,
+    util::unescape,
+    ParseOptions,
+};
+
+use self::{
+    block::{parse_blocks, Block, BlockKind, BlockTree, Line, SourceMap},
+    inline::{link_references, parse_inline, LinkReferences},
+};
+
+/// Depth beyond which containers, emphasis, links and ruby are not nested any further, so that
+/// building and rendering the tree cannot overflow the stack.
+const MAX_NESTING_DEPTH: usize = 100;
+
+pub(crate) fn parse(input: &str, options: &ParseOptions) -> Document {
+    let tree = parse_blocks(input, options);
+
+    let references = link_references(&tree.definitions);
+    let children = parse_children(&tree, &references, options, &tree.blocks[0]);
+    let metadata = Metadata {
+        title: children.iter().find_map(|node| match &node.kind {
+            ASTNode::Heading {
+                level: HeadingLevel::H1,
+                children,
+            } => Some(plain_text(children)),
+            _ => None,
+        }),
+    };
+
+    Document {
+        children,
+        definitions: tree.definitions,
+        metadata,
+    }
+}
+
+pub(crate) fn tokenize_leaf_blocks(input: &str, options: &ParseOptions) -> Vec<Token> {
+    parse_blocks(input, options)
+        .blocks
+        .iter()
+        .filter(|block| matches!(block.kind, BlockKind::Paragraph | BlockKind::Heading(_)))
+        .flat_map(|block| inline_tokens(&block.lines, options))
+        .collect()
+}
+
+fn parse_children(
+    tree: &BlockTree,
+    references: &LinkReferences,
+    options: &ParseOptions,
+    block: &Block,
+) -> Vec<Node> {
+    block
+        .children
+        .iter()
+        .map(|&child| parse_block(tree, references, options, &tree.blocks[child]))
+        .collect()
+}
+
+fn parse_block(
+    tree: &BlockTree,
+    references: &LinkReferences,
+    options: &ParseOptions,
+    block: &Block,
+) -> Node {
+    let kind = match &block.kind {
+        BlockKind::BlockQuote => {
+            ASTNode::BlockQuote(parse_children(tree, references, options, block))
+        }
+        BlockKind::List {
+            list_type,
+            marker,
+            start,
+            tight,
+        } => ASTNode::List {
+            list_type: list_type.clone(),
+            start: (*list_type == ListType::Ordered).then_some(*start),
+            delimiter: (*list_type == ListType::Ordered).then_some(*marker),
+            bullet: (*list_type != ListType::Ordered).then_some(*marker),
+            tight: *tight,
+            children: block
+                .children
+                .iter()
+                .map(|&child| {
+                    let item = &tree.blocks[child];
+                    let BlockKind::ListItem { checked, .. } = item.kind else {
+                        unreachable!()
+                    };
+
+                    ListItem {
+                        checked,
+                        children: parse_children(tree, references, options, item),
+                        span: item.span,
+                    }
+                })
+                .collect(),
+        },
+        BlockKind::Paragraph => ASTNode::Paragraph(parse_inline(
+            &inline_tokens(&block.lines, options),
+            references,
+        )),
+        BlockKind::Heading(level) => ASTNode::Heading {
+            level: level.clone(),
+            children: parse_inline(&inline_tokens(&block.lines, options), references),
+        },
+        BlockKind::ThematicBreak => ASTNode::HorizontalRule,
+        BlockKind::IndentedCode => {
+            // 末尾の空行はコードブロックに含めない
+            let end = block
+                .lines
+                .iter()
+                .rposition(|line| !line.text.trim().is_empty())
+                .map(|i| i + 1)
+                .unwrap_or_default();
+
+            ASTNode::CodeBlock {
+                language: String::new(),
+                info: String::new(),
+                text: code_text(&block.lines[..end]),
+            }
+        }
+        BlockKind::FencedCode { info, .. } => ASTNode::CodeBlock {
+            language: unescape(info.split_whitespace().next().unwrap_or_default()),
+            info: info.clone(),
+            text: code_text(&block.lines),
+        },
+        BlockKind::HtmlBlock { .. } => ASTNode::HtmlBlock(
+            block
+                .lines
+                .iter()
+                .map(|line| line.text.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        BlockKind::Document | BlockKind::ListItem { .. } => unreachable!(),
+    };
+
+    Node::new(kind, block.span)
+}
+
+/// Tokenizes the inline content of a leaf block, with spans pointing into the input.
+fn inline_tokens(lines: &[Line], options: &ParseOptions) -> Vec<Token> {
+    let content = lines
+        .iter()
+        .map(|line| line.text.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut tokens = tokenize(&content, options);
+    let source_map = SourceMap::new(lines);
+    for token in tokens.iter_mut() {
+        token.span = source_map.span(token.span);
+    }
+
+    tokens
+}
+
+fn code_text(lines: &[Line]) -> String {
+    lines
+        .iter()
+        .map(|line| format!("{}\n", line.text))
+        .collect()
+}
+
+fn raw_text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|t| match t.token_type {
+            TokenType::HardLineBreak => format!("{}\n", t.raw),
+            _ => t.raw.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MAX_NESTING_DEPTH;
+    use crate::{
+        ast::{ASTNode, HeadingLevel, LinkReferenceDefinition, ListItem, ListType, Metadata, Node},
+        parse, parse_with_options,
+        span::Span,
+        ParseOptions,
+    };
+
+    fn node(kind: ASTNode, start: usize, end: usize) -> Node {
+        Node::new(kind, Span::new(start, end))
+    }
+
+    fn text(text: &str, start: usize, end: usize) -> Node {
+        node(ASTNode::Text(text.to_string()), start, end)
+    }
+
+    fn soft_break(start: usize) -> Node {
+        node(ASTNode::SoftBreak, start, start + 1)
+    }
+
+    fn paragraph(content: &str, start: usize, end: usize) -> Node {
+        node(
+            ASTNode::Paragraph(vec![text(content, start, end)]),
+            start,
+            end,
+        )
+    }
+
+    #[test]
+    fn parse_document() {
+        // ドキュメント
+        let input = "Hello, World!\n\n# Title\n\n[link]: https://example.com \"title\"";
+        let document = parse(input);
+        assert_eq!(
+            document.children,
+            vec![
+                paragraph("Hello, World!", 0, 13),
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H1,
+                        children: vec![text("Title", 17, 22)],
+                    },
+                    15,
+                    22
+                ),
+            ]
+        );
+        assert_eq!(
+            document.definitions,
+            vec![LinkReferenceDefinition {
+                label: "link".to_string(),
+                destination: "https://example.com".to_string(),
+                title: Some("title".to_string()),
+                span: Span::new(24, 59),
+            }]
+        );
+        assert_eq!(
+            document.metadata,
+            Metadata {
+                title: Some("Title".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_heading() {
+        // 見出し
+        let input = "# Heading 1\n## Heading 2\n\nHeading 1\n===\nHeading 2\n---";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H1,
+                        children: vec![text("Heading 1", 2, 11)],
+                    },
+                    0,
+                    11
+                ),
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H2,
+                        children: vec![text("Heading 2", 15, 24)],
+                    },
+                    12,
+                    24
+                ),
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H1,
+                        children: vec![text("Heading 1", 26, 35)],
+                    },
+                    26,
+                    39
+                ),
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H2,
+                        children: vec![text("Heading 2", 40, 49)],
+                    },
+                    40,
+                    53
+                ),
+            ]
+        );
+
+        // 閉じる `#`、空の見出し、7つ以上の `#`、複数行の setext 見出し
+        let input = "   ## foo ##  \n#\n####### foo\nbar\n===";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H2,
+                        children: vec![text("foo", 6, 9)],
+                    },
+                    3,
+                    12
+                ),
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H1,
+                        children: vec![],
+                    },
+                    15,
+                    16
+                ),
+                node(
+                    ASTNode::Heading {
+                        level: HeadingLevel::H1,
+                        children: vec![
+                            text("####### foo", 17, 28),
+                            soft_break(28),
+                            text("bar", 29, 32),
+                        ],
+                    },
+                    17,
+                    36
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_paragraph() {
+        // 段落
+        let input = "Hello,\nWorld!\n\n---\nHello, World!";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::Paragraph(vec![
+                        text("Hello,", 0, 6),
+                        soft_break(6),
+                        text("World!", 7, 13),
+                    ]),
+                    0,
+                    13
+                ),
+                node(ASTNode::HorizontalRule, 15, 18),
+                paragraph("Hello, World!", 19, 32),
+            ]
+        );
+
+        // 改行と行末の空白
+        let input = "foo \nbar  \nbaz\\\nqux  ";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::Paragraph(vec![
+                    text("foo", 0, 3),
+                    soft_break(4),
+                    text("bar", 5, 8),
+                    node(ASTNode::HardBreak, 8, 11),
+                    text("baz", 11, 14),
+                    node(ASTNode::HardBreak, 14, 16),
+                    text("qux", 16, 19),
+                ]),
+                0,
+                19
+            )]
+        );
+
+        // 段落を中断しない行
+        let input = "windows:\n14. doors\n*\n    code";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::Paragraph(vec![
+                    text("windows:", 0, 8),
+                    soft_break(8),
+                    text("14. doors", 9, 18),
+                    soft_break(18),
+                    text("*", 19, 20),
+                    soft_break(20),
+                    text("code", 25, 29),
+                ]),
+                0,
+                29
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_many_lines() {
+        // 行数の多い段落でも位置は行ごとに求める
+        let input = "> a\n".repeat(50000);
+        let nodes = parse(&input).children;
+        let [Node {
+            kind: ASTNode::BlockQuote(children),
+            ..
+        }] = nodes.as_slice()
+        else {
+            panic!("{:?}", nodes);
+        };
+        let [Node {
+            kind: ASTNode::Paragraph(inlines),
+            span,
+        }] = children.as_slice()
+        else {
+            panic!("{:?}", children);
+        };
+        assert_eq!(*span, Span::new(2, input.len() - 1));
+        assert_eq!(inlines.len(), 50000 * 2 - 1);
+        assert_eq!(
+            inlines.last(),
+            Some(&text("a", input.len() - 2, input.len() - 1))
+        );
+    }
+
+    #[test]
+    fn parse_deep_nesting() {
+        // 入れ子の深さには上限がある
+        fn depth(nodes: &[Node]) -> usize {
+            nodes
+                .iter()
+                .map(|node| match &node.kind {
+                    ASTNode::BlockQuote(children)
+                    | ASTNode::Italic(children)
+                    | ASTNode::Image { alt: children, .. } => 1 + depth(children),
+                    ASTNode::List { children, .. } => {
+                        2 + children
+                            .iter()
+                            .map(|item| depth(&item.children))
+                            .max()
+                            .unwrap_or(0)
+                    }
+                    _ => 0,
+                })
+                .max()
+                .unwrap_or(0)
+        }
+
+        let input = format!("{}a", "> ".repeat(MAX_NESTING_DEPTH + 10));
+        let nodes = parse(&input).children;
+        assert_eq!(depth(&nodes), MAX_NESTING_DEPTH);
+
+        let input = "1. ".repeat(MAX_NESTING_DEPTH + 10);
+        let nodes = parse(&input).children;
+        assert_eq!(depth(&nodes), MAX_NESTING_DEPTH);
+
+        let input = format!(
+            "{}b{}",
+            "*a ".repeat(MAX_NESTING_DEPTH + 10),
+            "*".repeat(MAX_NESTING_DEPTH + 10)
+        );
+        let nodes = parse(&input).children;
+        let [Node {
+            kind: ASTNode::Paragraph(children),
+            ..
+        }] = nodes.as_slice()
+        else {
+            panic!("{:?}", nodes);
+        };
+        assert_eq!(depth(children), MAX_NESTING_DEPTH);
+
+        let input = format!(
+            "{}a{}",
+            "![".repeat(MAX_NESTING_DEPTH + 10),
+            "](b)".repeat(MAX_NESTING_DEPTH + 10)
+        );
+        let nodes = parse(&input).children;
+        let [Node {
+            kind: ASTNode::Paragraph(children),
+            ..
+        }] = nodes.as_slice()
+        else {
+            panic!("{:?}", nodes);
+        };
+        assert_eq!(depth(children), MAX_NESTING_DEPTH);
+    }
+
+    #[test]
+    fn parse_block_quote() {
+        // 引用
+        let input = "> Hello,\nWorld!\n>> Hello, World!";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::BlockQuote(vec![
+                    node(
+                        ASTNode::Paragraph(vec![
+                            text("Hello,", 2, 8),
+                            soft_break(8),
+                            text("World!", 9, 15),
+                        ]),
+                        2,
+                        15
+                    ),
+                    node(
+                        ASTNode::BlockQuote(vec![paragraph("Hello, World!", 19, 32)]),
+                        17,
+                        32
+                    ),
+                ]),
+                0,
+                32
+            )]
+        );
+
+        let input = "> Hello, World!";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::BlockQuote(vec![paragraph("Hello, World!", 2, 15)]),
+                0,
+                15
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_html_block() {
+        // HTMLブロック
+        let input = "<DIV class=\"a\">\n*foo*\n\nHello\n<span>\n\n<!-- a\n\nb -->";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::HtmlBlock("<DIV class=\"a\">\n*foo*".to_string()),
+                    0,
+                    21
+                ),
+                // 単独のタグは段落を中断しない
+                node(
+                    ASTNode::Paragraph(vec![
+                        text("Hello", 23, 28),
+                        soft_break(28),
+                        node(ASTNode::InlineHtml("<span>".to_string()), 29, 35),
+                    ]),
+                    23,
+                    35
+                ),
+                node(ASTNode::HtmlBlock("<!-- a\n\nb -->".to_string()), 37, 50),
+            ]
+        );
+
+        // HTMLブロックの種類
+        for input in [
+            "<pre>\naaa\n</pre>",
+            "<!--this is comment.-->",
+            "<?php\necho(\"this is PHP source code.\");\n?>",
+            "<!doctype html>",
+            "<![CDATA[<sender>John Smith</sender>]]>",
+            "<form>\naaa\n</form>",
+            "<form action=\"https://example.com\">aaa</form>",
+            "<any-tag>\naaa\n</any-tag>",
+            "<any-tag any-attr=\"any-value\">\naaa\n</any-tag>",
+        ] {
+            assert_eq!(
+                parse(input).children,
+                vec![node(ASTNode::HtmlBlock(input.to_string()), 0, input.len())]
+            );
+        }
+    }
+
+    #[test]
+    fn parse_code_block() {
+        // コードブロック
+        let input = "```rust\nfn main() {}\n```\n\n    Hello, World!";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::CodeBlock {
+                        language: "rust".to_string(),
+                        info: "rust".to_string(),
+                        text: "fn main() {}\n".to_string(),
+                    },
+                    0,
+                    24
+                ),
+                node(
+                    ASTNode::CodeBlock {
+                        language: "".to_string(),
+                        info: "".to_string(),
+                        text: "Hello, World!\n".to_string(),
+                    },
+                    26,
+                    43
+                ),
+            ]
+        );
+
+        // フェンスのインデントは各行から取り除く
+        let input = "  ~~~ rust,ignore `x`\n  a\n   b\n c\n```\n  ~~~~";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::CodeBlock {
+                    language: "rust,ignore".to_string(),
+                    info: "rust,ignore `x`".to_string(),
+                    text: "a\n b\nc\n```\n".to_string(),
+                },
+                2,
+                44
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_tab() {
+        // タブは4の倍数の列まで進める
+        let input = "\tfoo\tbar\n\n- a\n\n\t\tcode";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::CodeBlock {
+                        language: "".to_string(),
+                        info: "".to_string(),
+                        text: "foo\tbar\n".to_string(),
+                    },
+                    0,
+                    8
+                ),
+                node(
+                    ASTNode::List {
+                        list_type: ListType::Unordered,
+                        start: None,
+                        delimiter: None,
+                        bullet: Some('-'),
+                        tight: false,
+                        children: vec![ListItem {
+                            checked: None,
+                            children: vec![
+                                paragraph("a", 12, 13),
+                                node(
+                                    ASTNode::CodeBlock {
+                                        language: "".to_string(),
+                                        info: "".to_string(),
+                                        text: "  code\n".to_string(),
+                                    },
+                                    15,
+                                    21
+                                ),
+                            ],
+                            span: Span::new(10, 21),
+                        }],
+                    },
+                    10,
+                    21
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_list() {
+        // リスト
+        let input = "- Hello,\n- World!\n\n1. Hello,\n2. World!\n\n- [ ] Hello,\n- [x] World!";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::List {
+                        list_type: ListType::Unordered,
+                        start: None,
+                        delimiter: None,
+                        bullet: Some('-'),
+                        tight: true,
+                        children: vec![
+                            ListItem {
+                                checked: None,
+                                children: vec![paragraph("Hello,", 2, 8)],
+                                span: Span::new(0, 8),
+                            },
+                            ListItem {
+                                checked: None,
+                                children: vec![paragraph("World!", 11, 17)],
+                                span: Span::new(9, 17),
+                            },
+                        ],
+                    },
+                    0,
+                    17
+                ),
+                node(
+                    ASTNode::List {
+                        list_type: ListType::Ordered,
+                        start: Some(1),
+                        delimiter: Some('.'),
+                        bullet: None,
+                        tight: true,
+                        children: vec![
+                            ListItem {
+                                checked: None,
+                                children: vec![paragraph("Hello,", 22, 28)],
+                                span: Span::new(19, 28),
+                            },
+                            ListItem {
+                                checked: None,
+                                children: vec![paragraph("World!", 32, 38)],
+                                span: Span::new(29, 38),
+                            },
+                        ],
+                    },
+                    19,
+                    38
+                ),
+                node(
+                    ASTNode::List {
+                        list_type: ListType::CheckBox,
+                        start: None,
+                        delimiter: None,
+                        bullet: Some('-'),
+                        tight: true,
+                        children: vec![
+                            ListItem {
+                                checked: Some(false),
+                                children: vec![paragraph("Hello,", 46, 52)],
+                                span: Span::new(40, 52),
+                            },
+                            ListItem {
+                                checked: Some(true),
+                                children: vec![paragraph("World!", 59, 65)],
+                                span: Span::new(53, 65),
+                            },
+                        ],
+                    },
+                    40,
+                    65
+                ),
+            ]
+        );
+
+        // 2番目以降の項目と順序付きリストのチェックボックス
+        let input = "- a\n- [x] b\n\n1. [ ] c";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![
+                node(
+                    ASTNode::List {
+                        list_type: ListType::CheckBox,
+                        start: None,
+                        delimiter: None,
+                        bullet: Some('-'),
+                        tight: true,
+                        children: vec![
+                            ListItem {
+                                checked: None,
+                                children: vec![paragraph("a", 2, 3)],
+                                span: Span::new(0, 3),
+                            },
+                            ListItem {
+                                checked: Some(true),
+                                children: vec![paragraph("b", 10, 11)],
+                                span: Span::new(4, 11),
+                            },
+                        ],
+                    },
+                    0,
+                    11
+                ),
+                node(
+                    ASTNode::List {
+                        list_type: ListType::Ordered,
+                        start: Some(1),
+                        delimiter: Some('.'),
+                        bullet: None,
+                        tight: true,
+                        children: vec![ListItem {
+                            checked: Some(false),
+                            children: vec![paragraph("c", 20, 21)],
+                            span: Span::new(13, 21),
+                        }],
+                    },
+                    13,
+                    21
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_nested_blocks() {
+        // 入れ子のブロック
+        let input = "- a\n  - b\n\n  > c\nlazy";
+        let nodes = parse(input).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::List {
+                    list_type: ListType::Unordered,
+                    start: None,
+                    delimiter: None,
+                    bullet: Some('-'),
+                    tight: false,
+                    children: vec![ListItem {
+                        checked: None,
+                        children: vec![
+                            paragraph("a", 2, 3),
+                            node(
+                                ASTNode::List {
+                                    list_type: ListType::Unordered,
+                                    start: None,
+                                    delimiter: None,
+                                    bullet: Some('-'),
+                                    tight: true,
+                                    children: vec![ListItem {
+                                        checked: None,
+                                        children: vec![paragraph("b", 8, 9)],
+                                        span: Span::new(6, 9),
+                                    }],
+                                },
+                                6,
+                                9
+                            ),
+                            node(
+                                ASTNode::BlockQuote(vec![node(
+                                    ASTNode::Paragraph(vec![
+                                        text("c", 15, 16),
+                                        soft_break(16),
+                                        text("lazy", 17, 21),
+                                    ]),
+                                    15,
+                                    21
+                                )]),
+                                13,
+                                21
+                            ),
+                        ],
+                        span: Span::new(0, 21),
+                    }],
+                },
+                0,
+                21
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_strict() {
+        // 拡張記法を無効にした場合
+        let input = "- [ ] 漢字《かんじ》";
+        let nodes = parse_with_options(input, &ParseOptions::strict()).children;
+        assert_eq!(
+            nodes,
+            vec![node(
+                ASTNode::List {
+                    list_type: ListType::Unordered,
+                    start: None,
+                    delimiter: None,
+                    bullet: Some('-'),
+                    tight: true,
+                    children: vec![ListItem {
+                        checked: None,
+                        children: vec![paragraph("[ ] 漢字《かんじ》", 2, 27)],
+                        span: Span::new(0, 27),
+                    }],
+                },
+                0,
+                27
+            )]
+        );
+    }
+}
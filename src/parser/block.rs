@@ -0,0 +1,874 @@
+use std::borrow::Cow;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::{
+    ast::{LinkReferenceDefinition, ListType},
+    span::Span,
+    token::{HeadingLevel, TokenType},
+    tokenizer::{tokenize_link_reference_definitions, CLOSING_TAG, OPEN_TAG},
+    util::{link_destination, unescape},
+    ParseOptions,
+};
+
+use super::MAX_NESTING_DEPTH;
+
+static ATX_HEADING_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(#{1,6})(?:[ \t]+|$)").unwrap());
+static ATX_CLOSING_SEQUENCE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?:^|[ \t]+)#+[ \t]*$").unwrap());
+static SETEXT_HEADING_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:=+|-+)[ \t]*$").unwrap());
+static THEMATIC_BREAK_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:(?:\*[ \t]*){3,}|(?:_[ \t]*){3,}|(?:-[ \t]*){3,})$").unwrap());
+static CODE_FENCE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:`{3,}|~{3,})").unwrap());
+static CLOSING_CODE_FENCE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:`{3,}|~{3,})[ \t]*$").unwrap());
+/// Start conditions 1 to 6 of HTML blocks.
+static HTML_BLOCK_START_REGEXES: Lazy<[Regex; 6]> = Lazy::new(|| {
+    [
+        Regex::new(r"(?i)^<(?:pre|script|style|textarea)(?:[ \t>]|$)").unwrap(),
+        Regex::new(r"^<!--").unwrap(),
+        Regex::new(r"^<\?").unwrap(),
+        Regex::new(r"^<![A-Za-z]").unwrap(),
+        Regex::new(r"^<!\[CDATA\[").unwrap(),
+        Regex::new(r"(?i)^</?(?:address|article|aside|base|basefont|blockquote|body|caption|center|col|colgroup|dd|details|dialog|dir|div|dl|dt|fieldset|figcaption|figure|footer|form|frame|frameset|h1|h2|h3|h4|h5|h6|head|header|hr|html|iframe|legend|li|link|main|menu|menuitem|nav|noframes|ol|optgroup|option|p|param|search|section|summary|table|tbody|td|tfoot|th|thead|title|tr|track|ul)(?:[ \t>]|/>|$)").unwrap(),
+    ]
+});
+/// Start condition 7, a complete open or closing tag alone on the line.
+static HTML_BLOCK_TAG_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(&format!(r"^(?:{}|{})[ \t]*$", *OPEN_TAG, *CLOSING_TAG)).unwrap());
+/// End conditions of the HTML blocks of start conditions 1 to 5.
+static HTML_BLOCK_END_REGEXES: Lazy<[Regex; 5]> = Lazy::new(|| {
+    [
+        Regex::new(r"(?i)</(?:pre|script|style|textarea)>").unwrap(),
+        Regex::new(r"-->").unwrap(),
+        Regex::new(r"\?>").unwrap(),
+        Regex::new(r">").unwrap(),
+        Regex::new(r"\]\]>").unwrap(),
+    ]
+});
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum BlockKind {
+    Document,
+    BlockQuote,
+    List {
+        list_type: ListType,
+        /// Bullet character, or the delimiter of an ordered list.
+        marker: char,
+        /// Number of the first item of an ordered list.
+        start: u32,
+        /// Whether no items or their children are separated by blank lines.
+        tight: bool,
+    },
+    ListItem {
+        checked: Option<bool>,
+        /// Columns a line needs to be indented by to continue the item.
+        indent: usize,
+    },
+    Paragraph,
+    Heading(HeadingLevel),
+    ThematicBreak,
+    IndentedCode,
+    FencedCode {
+        fence: String,
+        info: String,
+        /// Columns the opening fence is indented by, removed from each content line.
+        indent: usize,
+    },
+    HtmlBlock {
+        /// Start condition in the spec, from 1 to 7. Blocks of conditions 6 and 7 end at a blank line.
+        condition: usize,
+    },
+}
+
+/// A line of block content, without container markers and the line ending.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Line {
+    pub(super) text: String,
+    pub(super) offset: usize,
+}
+
+#[derive(Debug)]
+pub(super) struct Block {
+    pub(super) kind: BlockKind,
+    pub(super) children: Vec<usize>,
+    pub(super) lines: Vec<Line>,
+    pub(super) span: Span,
+    parent: usize,
+    open: bool,
+}
+
+/// Block structure of a document. The root is at index 0.
+#[derive(Debug)]
+pub(super) struct BlockTree {
+    pub(super) blocks: Vec<Block>,
+    pub(super) definitions: Vec<LinkReferenceDefinition>,
+}
+
+enum Continuation {
+    Matched,
+    Unmatched,
+    /// The line closed the block and nothing is left to process.
+    Consumed,
+}
+
+#[derive(Clone)]
+struct LineScanner<'a> {
+    text: &'a str,
+    offset: usize,
+    pos: usize,
+    /// Column of `pos`, with tabs advancing to the next multiple of 4.
+    column: usize,
+    /// Whether the tab at `pos` has been consumed partially.
+    partially_consumed_tab: bool,
+}
+
+impl<'a> LineScanner<'a> {
+    fn new(text: &'a str, offset: usize) -> Self {
+        Self {
+            text,
+            offset,
+            pos: 0,
+            column: 0,
+            partially_consumed_tab: false,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    /// Rest of the line after the indentation.
+    fn nonspace(&self) -> &'a str {
+        self.rest().trim_start_matches([' ', '\t'])
+    }
+
+    /// Offset in the input of the first character after the indentation.
+    fn nonspace_offset(&self) -> usize {
+        self.offset + self.text.len() - self.nonspace().len()
+    }
+
+    /// Rest of the line, with the remaining columns of a partially consumed tab as spaces.
+    fn content(&self) -> Cow<'a, str> {
+        if self.partially_consumed_tab {
+            Cow::Owned(" ".repeat(4 - self.column % 4) + &self.rest()[1..])
+        } else {
+            Cow::Borrowed(self.rest())
+        }
+    }
+
+    /// Width of the indentation in columns.
+    fn indent(&self) -> usize {
+        let mut column = self.column;
+        for c in self.rest().chars() {
+            match c {
+                ' ' => column += 1,
+                '\t' => column += 4 - column % 4,
+                _ => break,
+            }
+        }
+
+        column - self.column
+    }
+
+    /// Advances by `count` bytes.
+    fn advance(&mut self, count: usize) {
+        let end = (self.pos + count).min(self.text.len());
+        for c in self.text[self.pos..end].chars() {
+            self.column += if c == '\t' { 4 - self.column % 4 } else { 1 };
+        }
+        self.pos = end;
+        self.partially_consumed_tab = false;
+    }
+
+    /// Advances by `count` columns. A tab wider than the remaining columns is consumed partially.
+    fn advance_columns(&mut self, mut count: usize) {
+        while count > 0 {
+            let Some(c) = self.rest().chars().next() else {
+                return;
+            };
+            let width = if c == '\t' { 4 - self.column % 4 } else { 1 };
+            if width > count {
+                self.column += count;
+                self.partially_consumed_tab = true;
+
+                return;
+            }
+
+            self.pos += c.len_utf8();
+            self.column += width;
+            self.partially_consumed_tab = false;
+            count -= width;
+        }
+    }
+
+    /// Skips a single space, or a column of a tab.
+    fn skip_space(&mut self) {
+        if self.rest().starts_with([' ', '\t']) {
+            self.advance_columns(1);
+        }
+    }
+
+    fn is_blank(&self) -> bool {
+        self.rest().trim().is_empty()
+    }
+
+    fn source_offset(&self) -> usize {
+        self.offset + self.pos
+    }
+}
+
+struct BlockParser<'a> {
+    input: &'a str,
+    options: &'a ParseOptions,
+    blocks: Vec<Block>,
+    definitions: Vec<LinkReferenceDefinition>,
+    tip: usize,
+    last_matched: usize,
+    all_closed: bool,
+}
+
+pub(super) fn parse_blocks(input: &str, options: &ParseOptions) -> BlockTree {
+    let mut parser = BlockParser {
+        input,
+        options,
+        blocks: vec![Block {
+            kind: BlockKind::Document,
+            children: vec![],
+            lines: vec![],
+            span: Span::new(0, input.len()),
+            parent: 0,
+            open: true,
+        }],
+        definitions: vec![],
+        tip: 0,
+        last_matched: 0,
+        all_closed: true,
+    };
+
+    let mut offset = 0;
+    for line in input.split_inclusive('\n') {
+        let text = line.strip_suffix('\n').unwrap_or(line);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        parser.parse_line(LineScanner::new(text, offset));
+        offset += line.len();
+    }
+    while parser.tip != 0 {
+        parser.finalize(parser.tip);
+    }
+
+    BlockTree {
+        blocks: parser.blocks,
+        definitions: parser.definitions,
+    }
+}
+
+impl BlockParser<'_> {
+    fn parse_line(&mut self, mut line: LineScanner) {
+        // 開いているブロックが行を継続するか確認する
+        let mut container = 0;
+        let mut all_matched = true;
+        while let Some(&child) = self.blocks[container].children.last() {
+            if !self.blocks[child].open {
+                break;
+            }
+            match self.continues(child, &mut line) {
+                Continuation::Matched => container = child,
+                Continuation::Unmatched => {
+                    all_matched = false;
+
+                    break;
+                }
+                Continuation::Consumed => return,
+            }
+        }
+        self.last_matched = container;
+        self.all_closed = all_matched && container == self.tip;
+
+        // 新しいブロックの開始を確認する
+        let mut started = false;
+        if !matches!(
+            self.blocks[container].kind,
+            BlockKind::IndentedCode | BlockKind::FencedCode { .. } | BlockKind::HtmlBlock { .. }
+        ) {
+            loop {
+                let indent = line.indent();
+                if indent >= 4 {
+                    if self.blocks[self.tip].kind != BlockKind::Paragraph && !line.is_blank() {
+                        let start = line.source_offset();
+                        line.advance_columns(4);
+                        container = self.add_child(container, BlockKind::IndentedCode, start);
+                        started = true;
+                    }
+
+                    break;
+                }
+                if line.is_blank() {
+                    break;
+                }
+
+                let rest = line.nonspace();
+                let start = line.nonspace_offset();
+                // 入れ子が深すぎる場合は新しいコンテナを開始しない
+                if rest.starts_with('>') && self.depth(container) < MAX_NESTING_DEPTH {
+                    line.advance_columns(indent);
+                    line.advance(1);
+                    line.skip_space();
+                    container = self.add_child(container, BlockKind::BlockQuote, start);
+                    self.blocks[container].span.end = start + 1;
+                    started = true;
+                } else if let Some((level, len)) = atx_heading(rest) {
+                    line.advance_columns(indent);
+                    line.advance(len);
+                    container = self.add_child(container, BlockKind::Heading(level), start);
+                    self.blocks[container].span.end = start + rest[..len].trim_end().len();
+                    self.push_line(container, &line, true);
+                    // 閉じる `#` の並びは見出しの内容に含めない
+                    if let Some(heading) = self.blocks[container].lines.last_mut() {
+                        let end = ATX_CLOSING_SEQUENCE_REGEX
+                            .find(&heading.text)
+                            .map_or(heading.text.len(), |m| m.start());
+                        heading.text.truncate(heading.text[..end].trim_end().len());
+                    }
+                    self.finalize(container);
+                    started = true;
+
+                    break;
+                } else if let Some(fence) = CODE_FENCE_REGEX.find(rest) {
+                    let info = rest[fence.end()..].trim().to_string();
+                    if fence.as_str().starts_with('`') && info.contains('`') {
+                        break;
+                    }
+                    let kind = BlockKind::FencedCode {
+                        fence: fence.as_str().to_string(),
+                        info,
+                        indent,
+                    };
+                    let fenced_code = self.add_child(container, kind, start);
+                    self.blocks[fenced_code].span.end = start + rest.trim_end().len();
+
+                    return;
+                } else if let Some(condition) = html_block_start_condition(rest).filter(|&c| {
+                    // 7番目の条件の HTML ブロックは段落を中断できない
+                    c < 7
+                        || (self.blocks[container].kind != BlockKind::Paragraph
+                            && (self.all_closed
+                                || self.blocks[self.tip].kind != BlockKind::Paragraph))
+                }) {
+                    container =
+                        self.add_child(container, BlockKind::HtmlBlock { condition }, start);
+                    started = true;
+
+                    break;
+                } else if self.blocks[container].kind == BlockKind::Paragraph
+                    && SETEXT_HEADING_REGEX.is_match(rest)
+                    && self.parse_setext_heading(container, rest, start)
+                {
+                    return;
+                } else if THEMATIC_BREAK_REGEX.is_match(rest) {
+                    container = self.add_child(container, BlockKind::ThematicBreak, start);
+                    self.blocks[container].span.end = start + rest.trim_end().len();
+                    self.finalize(container);
+                    started = true;
+
+                    break;
+                } else if let Some(item) = self.parse_list_item(container, &mut line, indent) {
+                    container = item;
+                    started = true;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        // 遅延継続行
+        if !started
+            && !self.all_closed
+            && !line.is_blank()
+            && self.blocks[self.tip].kind == BlockKind::Paragraph
+        {
+            self.push_line(self.tip, &line, true);
+
+            return;
+        }
+
+        self.close_unmatched();
+        match self.blocks[container].kind {
+            BlockKind::Paragraph => self.push_line(container, &line, true),
+            BlockKind::IndentedCode | BlockKind::FencedCode { .. } => {
+                self.push_line(container, &line, false)
+            }
+            BlockKind::HtmlBlock { .. } => {
+                self.push_line(container, &line, false);
+                if end_condition_reached(&self.blocks[container].kind, line.rest()) {
+                    self.finalize(container);
+                }
+            }
+            BlockKind::Heading(_) | BlockKind::ThematicBreak => {}
+            _ if !line.is_blank() => {
+                let start = line.nonspace_offset();
+                let paragraph = self.add_child(container, BlockKind::Paragraph, start);
+                self.push_line(paragraph, &line, true);
+            }
+            _ => {}
+        }
+    }
+
+    fn continues(&mut self, id: usize, line: &mut LineScanner) -> Continuation {
+        let indent = line.indent();
+        match &self.blocks[id].kind {
+            BlockKind::BlockQuote => {
+                if indent >= 4 || !line.nonspace().starts_with('>') {
+                    return Continuation::Unmatched;
+                }
+                self.blocks[id].span.end = line.nonspace_offset() + 1;
+                line.advance_columns(indent);
+                line.advance(1);
+                line.skip_space();
+            }
+            BlockKind::List { .. } => {}
+            BlockKind::ListItem {
+                indent: required, ..
+            } => {
+                if line.is_blank() {
+                    // 空の項目の後に空行が続いた場合は項目を閉じる
+                    if self.blocks[id].children.is_empty() {
+                        return Continuation::Unmatched;
+                    }
+                    line.advance_columns(indent);
+                } else if indent >= *required {
+                    line.advance_columns(*required);
+                } else {
+                    return Continuation::Unmatched;
+                }
+            }
+            BlockKind::Paragraph if line.is_blank() => return Continuation::Unmatched,
+            BlockKind::Paragraph => {}
+            BlockKind::IndentedCode => {
+                if indent >= 4 {
+                    line.advance_columns(4);
+                } else if line.is_blank() {
+                    line.advance_columns(indent);
+                } else {
+                    return Continuation::Unmatched;
+                }
+            }
+            BlockKind::FencedCode {
+                fence,
+                indent: fence_indent,
+                ..
+            } => {
+                let rest = line.nonspace();
+                // 閉じるフェンスは開くフェンスと同じ文字で、同じ長さ以上である必要がある
+                if indent < 4
+                    && CLOSING_CODE_FENCE_REGEX.is_match(rest)
+                    && rest.starts_with(&fence[..1])
+                    && rest.trim_end().len() >= fence.len()
+                {
+                    self.blocks[id].span.end = line.nonspace_offset() + rest.trim_end().len();
+                    self.finalize(id);
+
+                    return Continuation::Consumed;
+                }
+
+                for _ in 0..*fence_indent {
+                    if !line.rest().starts_with([' ', '\t']) {
+                        break;
+                    }
+                    line.advance_columns(1);
+                }
+            }
+            BlockKind::HtmlBlock { condition: 6 | 7 } if line.is_blank() => {
+                return Continuation::Unmatched
+            }
+            BlockKind::HtmlBlock { .. } => {}
+            BlockKind::Document | BlockKind::Heading(_) | BlockKind::ThematicBreak => {
+                return Continuation::Unmatched
+            }
+        }
+
+        Continuation::Matched
+    }
+
+    fn parse_setext_heading(&mut self, paragraph: usize, rest: &str, start: usize) -> bool {
+        self.parse_link_reference_definitions(paragraph);
+        if self.blocks[paragraph].lines.is_empty() {
+            return false;
+        }
+
+        let level = if rest.starts_with('=') {
+            HeadingLevel::H1
+        } else {
+            HeadingLevel::H2
+        };
+        self.blocks[paragraph].kind = BlockKind::Heading(level);
+        self.blocks[paragraph].span.end = start + rest.trim_end().len();
+        self.close_unmatched();
+        self.finalize(paragraph);
+
+        true
+    }
+
+    fn parse_list_item(
+        &mut self,
+        container: usize,
+        line: &mut LineScanner,
+        indent: usize,
+    ) -> Option<usize> {
+        if self.depth(container) >= MAX_NESTING_DEPTH {
+            return None;
+        }
+        let rest = line.nonspace();
+        let start = line.nonspace_offset();
+
+        let (marker, marker_width, number) = match rest.chars().next()? {
+            c @ ('-' | '+' | '*') => (c, 1, 1),
+            _ => {
+                let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+                match rest[digits..].chars().next() {
+                    Some(c @ ('.' | ')')) if (1..=9).contains(&digits) => {
+                        (c, digits + 1, rest[..digits].parse().ok()?)
+                    }
+                    _ => return None,
+                }
+            }
+        };
+        let after_marker = &rest[marker_width..];
+        if !after_marker.is_empty() && !after_marker.starts_with([' ', '\t']) {
+            return None;
+        }
+        // 空の項目と 1 以外から始まる順序付きリストは段落を中断できない
+        if self.blocks[container].kind == BlockKind::Paragraph
+            && (after_marker.trim_start_matches([' ', '\t']).is_empty()
+                || (marker_width > 1 && number != 1))
+        {
+            return None;
+        }
+
+        line.advance_columns(indent);
+        line.advance(marker_width);
+        let marker_end = line.clone();
+        let spaces = line.indent();
+        // 5つ以上の空白はインデントされたコードブロックとして項目の内容に含める
+        let padding = if line.is_blank() || spaces >= 5 {
+            *line = marker_end;
+            line.skip_space();
+
+            marker_width + 1
+        } else {
+            line.advance_columns(spaces);
+
+            marker_width + spaces
+        };
+
+        let checked = match line.rest().get(..4) {
+            Some("[ ] ") if self.options.check_list => Some(false),
+            Some("[x] ") if self.options.check_list => Some(true),
+            _ => None,
+        };
+        if checked.is_some() {
+            line.advance(4);
+        }
+        let list_type = match (marker, checked) {
+            ('.' | ')', _) => ListType::Ordered,
+            (_, Some(_)) => ListType::CheckBox,
+            _ => ListType::Unordered,
+        };
+
+        let list = match &mut self.blocks[container].kind {
+            BlockKind::List {
+                marker: m,
+                list_type,
+                ..
+            } if *m == marker => {
+                // 2番目以降の項目にチェックボックスがあってもチェックリストにする
+                if checked.is_some() && *list_type == ListType::Unordered {
+                    *list_type = ListType::CheckBox;
+                }
+
+                container
+            }
+            _ => {
+                let kind = BlockKind::List {
+                    list_type,
+                    marker,
+                    start: number,
+                    tight: true,
+                };
+                self.add_child(container, kind, start)
+            }
+        };
+        let item = self.add_child(
+            list,
+            BlockKind::ListItem {
+                checked,
+                indent: indent + padding,
+            },
+            start,
+        );
+        self.blocks[item].span.end = line.source_offset();
+
+        Some(item)
+    }
+
+    fn parse_link_reference_definitions(&mut self, paragraph: usize) {
+        let lines = &self.blocks[paragraph].lines;
+        let content = lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (tokens, consumed) = tokenize_link_reference_definitions(&content);
+        if consumed == 0 {
+            return;
+        }
+
+        let source_map = SourceMap::new(lines);
+        for token in tokens {
+            if let TokenType::LinkReferenceDefinition(label, destination, title) = token.token_type
+            {
+                self.definitions.push(LinkReferenceDefinition {
+                    label,
+                    destination: link_destination(&destination),
+                    title: title.as_deref().map(unescape),
+                    span: source_map.span(token.span),
+                });
+            }
+        }
+
+        let mut pos = 0;
+        let count = lines
+            .iter()
+            .take_while(|line| {
+                let is_consumed = pos < consumed;
+                pos += line.text.len() + 1;
+
+                is_consumed
+            })
+            .count();
+        let block = &mut self.blocks[paragraph];
+        block.lines.drain(..count);
+        if let Some(line) = block.lines.first() {
+            block.span.start = line.offset;
+        }
+    }
+
+    /// Number of blocks `id` is nested in.
+    fn depth(&self, id: usize) -> usize {
+        let mut depth = 0;
+        let mut id = id;
+        while id != 0 {
+            id = self.blocks[id].parent;
+            depth += 1;
+        }
+
+        depth
+    }
+
+    fn add_child(&mut self, parent: usize, kind: BlockKind, start: usize) -> usize {
+        self.close_unmatched();
+
+        let mut parent = parent;
+        while !can_contain(&self.blocks[parent].kind, &kind) {
+            self.finalize(parent);
+            parent = self.blocks[parent].parent;
+        }
+
+        let id = self.blocks.len();
+        self.blocks.push(Block {
+            kind,
+            children: vec![],
+            lines: vec![],
+            span: Span::new(start, start),
+            parent,
+            open: true,
+        });
+        self.blocks[parent].children.push(id);
+        self.tip = id;
+
+        id
+    }
+
+    fn push_line(&mut self, id: usize, line: &LineScanner, trim_start: bool) {
+        let content = line.content();
+        let text = if trim_start {
+            content.trim_start()
+        } else {
+            &content
+        };
+        // 部分的に消費したタブを空白に置き換えた場合は、タブの位置を行の開始とする
+        let offset = (line.offset + line.text.len())
+            .saturating_sub(text.len())
+            .max(line.source_offset());
+
+        let block = &mut self.blocks[id];
+        if !text.trim().is_empty() {
+            block.span.end = line.offset + line.text.trim_end().len();
+        }
+        block.lines.push(Line {
+            text: text.to_string(),
+            offset,
+        });
+    }
+
+    fn close_unmatched(&mut self) {
+        if self.all_closed {
+            return;
+        }
+
+        while self.tip != self.last_matched {
+            self.finalize(self.tip);
+        }
+        self.all_closed = true;
+    }
+
+    fn finalize(&mut self, id: usize) {
+        self.blocks[id].open = false;
+        let parent = self.blocks[id].parent;
+
+        match self.blocks[id].kind {
+            BlockKind::Paragraph => {
+                self.parse_link_reference_definitions(id);
+                if let Some(line) = self.blocks[id].lines.last_mut() {
+                    line.text.truncate(line.text.trim_end().len());
+                } else {
+                    // リンク参照定義のみの段落は取り除く
+                    self.blocks[parent].children.retain(|&child| child != id);
+                }
+            }
+            BlockKind::Heading(_) => {
+                if let Some(line) = self.blocks[id].lines.last_mut() {
+                    line.text.truncate(line.text.trim_end().len());
+                }
+            }
+            BlockKind::List { .. } => {
+                self.set_span_end_to_last_child(id);
+                // 項目の間か、項目の子の間に空行があればゆるいリストとする
+                let loose = self.separated_by_blank_line(&self.blocks[id].children)
+                    || self.blocks[id]
+                        .children
+                        .iter()
+                        .any(|&item| self.separated_by_blank_line(&self.blocks[item].children));
+                if let BlockKind::List { tight, .. } = &mut self.blocks[id].kind {
+                    *tight = !loose;
+                }
+            }
+            BlockKind::BlockQuote | BlockKind::ListItem { .. } => {
+                self.set_span_end_to_last_child(id)
+            }
+            _ => {}
+        }
+
+        if self.tip == id {
+            self.tip = parent;
+        }
+    }
+
+    fn set_span_end_to_last_child(&mut self, id: usize) {
+        let end = self.blocks[id]
+            .children
+            .last()
+            .map(|&child| self.blocks[child].span.end);
+        let block = &mut self.blocks[id];
+        block.span.end = block.span.end.max(end.unwrap_or_default());
+    }
+
+    fn separated_by_blank_line(&self, siblings: &[usize]) -> bool {
+        siblings.windows(2).any(|pair| {
+            self.input
+                .get(self.blocks[pair[0]].span.end..self.blocks[pair[1]].span.start)
+                .is_some_and(|between| between.matches('\n').count() >= 2)
+        })
+    }
+}
+
+fn can_contain(parent: &BlockKind, child: &BlockKind) -> bool {
+    match parent {
+        BlockKind::Document | BlockKind::BlockQuote | BlockKind::ListItem { .. } => {
+            !matches!(child, BlockKind::ListItem { .. })
+        }
+        BlockKind::List { .. } => matches!(child, BlockKind::ListItem { .. }),
+        _ => false,
+    }
+}
+
+/// Returns the level of the ATX heading beginning at `line` and the length of its opening sequence.
+fn atx_heading(line: &str) -> Option<(HeadingLevel, usize)> {
+    let caps = ATX_HEADING_REGEX.captures(line)?;
+    let level = HeadingLevel::try_from(caps[1].len() as u8).ok()?;
+
+    Some((level, caps[0].len()))
+}
+
+/// Returns the start condition of the HTML block beginning at `line`, if any.
+fn html_block_start_condition(line: &str) -> Option<usize> {
+    if let Some(i) = HTML_BLOCK_START_REGEXES
+        .iter()
+        .position(|regex| regex.is_match(line))
+    {
+        return Some(i + 1);
+    }
+
+    let caps = HTML_BLOCK_TAG_REGEX.captures(line)?;
+    let name = caps.get(1).or(caps.get(2))?.as_str().to_ascii_lowercase();
+    (!matches!(name.as_str(), "pre" | "script" | "style" | "textarea")).then_some(7)
+}
+
+fn end_condition_reached(kind: &BlockKind, line: &str) -> bool {
+    match kind {
+        BlockKind::HtmlBlock { condition } if *condition <= 5 => {
+            HTML_BLOCK_END_REGEXES[condition - 1].is_match(line)
+        }
+        _ => false,
+    }
+}
+
+/// Converts offsets into the content of `lines` joined by `'\n'` into offsets in the input.
+pub(super) struct SourceMap<'a> {
+    lines: &'a [Line],
+    /// Offset of each line in the joined content.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub(super) fn new(lines: &'a [Line]) -> Self {
+        let line_starts = lines
+            .iter()
+            .scan(0, |pos, line| {
+                let start = *pos;
+                *pos += line.text.len() + 1;
+
+                Some(start)
+            })
+            .collect();
+
+        Self { lines, line_starts }
+    }
+
+    fn offset(&self, offset: usize) -> usize {
+        let line = self
+            .line_starts
+            .partition_point(|&start| start <= offset)
+            .saturating_sub(1);
+        match self.lines.get(line) {
+            Some(l) if offset <= self.line_starts[line] + l.text.len() => {
+                l.offset + offset - self.line_starts[line]
+            }
+            _ => self
+                .lines
+                .last()
+                .map(|line| line.offset + line.text.len())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub(super) fn span(&self, span: Span) -> Span {
+        let start = self.offset(span.start);
+        if span.is_empty() {
+            return Span::new(start, start);
+        }
+
+        Span::new(start, self.offset(span.end - 1) + 1)
+    }
+}
@@ -0,0 +1,1192 @@
+use std::collections::HashMap;
+
+use unicode_categories::UnicodeCategories;
+use unicode_script::{ScriptExtension, UnicodeScript};
+
+use crate::{
+    ast::{ASTNode, LinkReferenceDefinition, Node},
+    entity::{decode_entities, decode_entities_with_offsets},
+    span::Span,
+    token::{DelimiterType, Token, TokenType},
+    util::{link_destination, normalize_uri, unescape},
+};
+
+use super::{raw_text, MAX_NESTING_DEPTH};
+
+/// The longest link label in characters.
+const MAX_LABEL_LENGTH: usize = 999;
+
+pub(super) type LinkReferences<'a> = HashMap<String, &'a LinkReferenceDefinition>;
+
+enum Inline {
+    Node(Node),
+    Delimiter {
+        char: char,
+        count: usize,
+        /// Length of the delimiter run before any delimiters were used.
+        length: usize,
+        can_open: bool,
+        can_close: bool,
+        span: Span,
+    },
+    LinkOpening {
+        is_image: bool,
+        active: bool,
+        /// Index of the opening token.
+        index: usize,
+        span: Span,
+    },
+    RubyTargetOpening(Span),
+}
+
+pub(super) fn parse_inline(tokens: &[Token], references: &LinkReferences) -> Vec<Node> {
+    let mut items = vec![];
+    // 閉じられていないリンクの開始とルビを振る文字列の開始の位置
+    let mut links = vec![];
+    let mut ruby_targets = vec![];
+    // 開始記号ごとに末尾まで探し直さないよう、ルビの閉じ記号の位置を覚えておく
+    let mut ruby_closing = 0;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = &tokens[i];
+        match &token.token_type {
+            TokenType::CodeSpan => {
+                let closing = tokens[i + 1..]
+                    .iter()
+                    .position(|t| t.token_type == TokenType::CodeSpan && t.raw == token.raw);
+                if let Some(len) = closing {
+                    let code = code_span_text(&raw_text(&tokens[i + 1..i + 1 + len]));
+                    let end = tokens[i + 1 + len].span.end;
+                    items.push(Inline::Node(Node::new(
+                        ASTNode::Code(code),
+                        Span::new(token.span.start, end),
+                    )));
+                    i += len + 2;
+
+                    continue;
+                }
+
+                push_text(&mut items, &token.raw, token.span);
+            }
+            TokenType::Emphasis(delimiter_type) => items.push(Inline::Delimiter {
+                char: token.raw.chars().next().unwrap_or('*'),
+                count: token.raw.chars().count(),
+                length: token.raw.chars().count(),
+                can_open: *delimiter_type != DelimiterType::RightFlanking,
+                can_close: *delimiter_type != DelimiterType::LeftFlanking,
+                span: token.span,
+            }),
+            // 参照先が見つからなかったラベルはリンクテキストとして扱う
+            TokenType::LinkTextOpening
+            | TokenType::LinkLabelMatchOpening
+            | TokenType::ImageTextOpening => {
+                // 入れ子が深すぎる場合は開始として扱わない
+                if links.len() >= MAX_NESTING_DEPTH {
+                    push_text(&mut items, &token.raw, token.span);
+                } else {
+                    links.push(items.len());
+                    items.push(Inline::LinkOpening {
+                        is_image: token.token_type == TokenType::ImageTextOpening,
+                        active: true,
+                        index: i,
+                        span: token.span,
+                    });
+                }
+            }
+            TokenType::LinkTextClosing | TokenType::LinkLabelMatchClosing => {
+                i = parse_link_closing(
+                    &mut items,
+                    &mut links,
+                    &mut ruby_targets,
+                    tokens,
+                    i,
+                    references,
+                );
+
+                continue;
+            }
+            TokenType::AutoLink => {
+                let text = token.raw[1..token.raw.len() - 1].to_string();
+                let href = if text.contains(':') {
+                    normalize_uri(&text)
+                } else {
+                    format!("mailto:{}", normalize_uri(&text))
+                };
+                let text_span = Span::new(token.span.start + 1, token.span.end - 1);
+                items.push(Inline::Node(Node::new(
+                    ASTNode::Link {
+                        href,
+                        title: None,
+                        children: vec![Node::new(ASTNode::Text(text), text_span)],
+                    },
+                    token.span,
+                )));
+            }
+            TokenType::RubyTargetOpening if ruby_targets.len() < MAX_NESTING_DEPTH => {
+                ruby_targets.push(items.len());
+                items.push(Inline::RubyTargetOpening(token.span));
+            }
+            TokenType::RubyTextOpening => {
+                if ruby_closing <= i {
+                    ruby_closing = tokens[i + 1..]
+                        .iter()
+                        .position(|t| t.token_type == TokenType::RubyTextClosing)
+                        .map_or(tokens.len(), |len| i + 1 + len);
+                }
+                if ruby_closing < tokens.len() && ruby_closing > i + 1 {
+                    let text = &tokens[i + 1..ruby_closing];
+                    let end = tokens[ruby_closing].span.end;
+                    let target = ruby_targets
+                        .last()
+                        .copied()
+                        .filter(|&target| links.last().is_none_or(|&link| link < target));
+                    if parse_ruby(&mut items, target, &tokens[..i], text, end) {
+                        if target.is_some() {
+                            ruby_targets.pop();
+                        }
+                        i = ruby_closing + 1;
+
+                        continue;
+                    }
+                }
+
+                push_text(&mut items, &token.raw, token.span);
+            }
+            TokenType::SoftLineBreak => {
+                items.push(Inline::Node(Node::new(ASTNode::SoftBreak, token.span)))
+            }
+            TokenType::HardLineBreak => {
+                items.push(Inline::Node(Node::new(ASTNode::HardBreak, token.span)))
+            }
+            TokenType::Text => push_text(&mut items, &decode_entities(&token.raw), token.span),
+            TokenType::EscapedChar => push_text(&mut items, &token.raw[1..], token.span),
+            TokenType::RawHTML => items.push(Inline::Node(Node::new(
+                ASTNode::InlineHtml(token.raw.clone()),
+                token.span,
+            ))),
+            _ => push_text(&mut items, &token.raw, token.span),
+        }
+        i += 1;
+    }
+
+    process_emphasis(&mut items, 0);
+    into_nodes(items)
+}
+
+fn parse_link_closing(
+    items: &mut Vec<Inline>,
+    links: &mut Vec<usize>,
+    ruby_targets: &mut Vec<usize>,
+    tokens: &[Token],
+    index: usize,
+    references: &LinkReferences,
+) -> usize {
+    let token = &tokens[index];
+    let Some(opener) = links.pop() else {
+        push_text(items, "]", token.span);
+
+        return index + 1;
+    };
+    let Inline::LinkOpening {
+        is_image,
+        active,
+        index: opening,
+        span,
+    } = items[opener]
+    else {
+        unreachable!()
+    };
+
+    let resolved = if active {
+        resolve_link(tokens, opening, index, references)
+    } else {
+        None
+    };
+    let Some((href, title, next)) = resolved else {
+        let text = if is_image { "![" } else { "[" }.to_string();
+        items[opener] = Inline::Node(Node::new(ASTNode::Text(text), span));
+        push_text(items, "]", token.span);
+
+        return index + 1;
+    };
+    let span = Span::new(span.start, tokens[next - 1].span.end);
+
+    process_emphasis(items, opener + 1);
+    let children = into_nodes(items.drain(opener + 1..).collect());
+    items.truncate(opener);
+    ruby_targets.truncate(ruby_targets.partition_point(|&target| target < opener));
+
+    if is_image {
+        items.push(Inline::Node(Node::new(
+            ASTNode::Image {
+                href,
+                title,
+                alt: children,
+            },
+            span,
+        )));
+    } else {
+        // リンクの中にリンクは置けないため、それより前のリンクの開始を無効にする。
+        // 無効な開始より前はすでに無効になっている
+        for &link in links.iter().rev() {
+            if let Inline::LinkOpening {
+                is_image: false,
+                active,
+                ..
+            } = &mut items[link]
+            {
+                if !*active {
+                    break;
+                }
+                *active = false;
+            }
+        }
+        items.push(Inline::Node(Node::new(
+            ASTNode::Link {
+                href,
+                title,
+                children,
+            },
+            span,
+        )));
+    }
+
+    next
+}
+
+/// Resolves the destination of the link whose text spans `tokens[opening..=closing]`.
+///
+/// Returns the href, the title and the index of the token following the link.
+fn resolve_link(
+    tokens: &[Token],
+    opening: usize,
+    closing: usize,
+    references: &LinkReferences,
+) -> Option<(String, Option<String>, usize)> {
+    let next = tokens.get(closing + 1).map(|t| &t.token_type);
+
+    // インラインリンク
+    if next == Some(&TokenType::LinkDestOpening) {
+        let len = tokens[closing + 1..]
+            .iter()
+            .position(|t| t.token_type == TokenType::LinkDestClosing)?;
+        let dest_tokens = &tokens[closing + 1..closing + 1 + len];
+        let href = dest_tokens
+            .iter()
+            .find(|t| t.token_type == TokenType::LinkDest)
+            .map(|t| normalize_uri(&link_destination(&t.raw)))
+            .unwrap_or_default();
+        let title = dest_tokens
+            .iter()
+            .find(|t| t.token_type == TokenType::LinkTitle)
+            .map(|t| unescape(&t.raw));
+
+        return Some((href, title, closing + len + 2));
+    }
+
+    // 参照リンク
+    let (label, next) = if next == Some(&TokenType::LinkLabelMatchOpening) {
+        let len = tokens[closing + 1..]
+            .iter()
+            .position(|t| t.token_type == TokenType::LinkLabelMatchClosing)?;
+
+        // `[foo][]`の場合はリンクテキストをラベルとして扱う
+        (
+            if len == 1 {
+                opening + 1..closing
+            } else {
+                closing + 2..closing + 1 + len
+            },
+            closing + len + 2,
+        )
+    } else {
+        (opening + 1..closing, closing + 1)
+    };
+    let label = link_label(&tokens[label])?;
+    let definition = references.get(&normalize_label(&label))?;
+
+    Some((
+        normalize_uri(&definition.destination),
+        definition.title.clone(),
+        next,
+    ))
+}
+
+/// Builds the label of a reference link, or `None` if it is longer than 999 characters.
+///
+/// The label is not built when its source is longer than any label can be, as `[` may be far away.
+fn link_label(tokens: &[Token]) -> Option<String> {
+    let (Some(first), Some(last)) = (tokens.first(), tokens.last()) else {
+        return Some(String::new());
+    };
+    // 1文字は最大4バイト
+    if last.span.end - first.span.start > MAX_LABEL_LENGTH * 4 {
+        return None;
+    }
+
+    let label = raw_text(tokens);
+    (label.chars().count() <= MAX_LABEL_LENGTH).then_some(label)
+}
+
+/// Link reference definitions keyed by normalized label. The first definition of a label wins.
+pub(super) fn link_references(definitions: &[LinkReferenceDefinition]) -> LinkReferences<'_> {
+    let mut references = LinkReferences::new();
+    for definition in definitions {
+        let label = normalize_label(&definition.label);
+        if !label.is_empty() {
+            references.entry(label).or_insert(definition);
+        }
+    }
+
+    references
+}
+
+/// Case-folds a link label and collapses its internal whitespace.
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .to_uppercase()
+}
+
+/// Builds a ruby from the text tokens between `《` and `》` if the items end with its target.
+/// `target` is the index of the `｜` the target starts at, if any.
+///
+/// The text is only built once the target is found, as `》` may be far away.
+fn parse_ruby(
+    items: &mut Vec<Inline>,
+    target: Option<usize>,
+    preceding: &[Token],
+    text: &[Token],
+    end: usize,
+) -> bool {
+    // `｜`でルビを振る文字列が指定されている場合
+    if let Some(opener) = target {
+        if opener + 1 == items.len() {
+            return false;
+        }
+        let Inline::RubyTargetOpening(span) = items[opener] else {
+            unreachable!()
+        };
+
+        process_emphasis(items, opener + 1);
+        let target = into_nodes(items.drain(opener + 1..).collect());
+        items.truncate(opener);
+        items.push(Inline::Node(Node::new(
+            ASTNode::Ruby {
+                target,
+                text: ruby_text(text),
+            },
+            Span::new(span.start, end),
+        )));
+
+        return true;
+    }
+
+    // 指定されていない場合は直前の同一unicode scriptの文字列にルビを振る
+    let Some(Inline::Node(Node {
+        kind: ASTNode::Text(prev),
+        span: prev_span,
+    })) = items.last_mut()
+    else {
+        return false;
+    };
+    // `ー`のように複数の用字で使われる文字は、共通する用字が残る間は同じ文字列とみなす
+    let mut scripts = ScriptExtension::default();
+    let split_at = prev
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| {
+            if c.is_whitespace() || c.is_punctuation() || c.is_symbol() {
+                return false;
+            }
+            let common = scripts.intersection(c.script_extension());
+            if common.is_empty() || common.is_common() || common.is_inherited() {
+                return false;
+            }
+            scripts = common;
+
+            true
+        })
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(prev.len());
+    if split_at == prev.len() {
+        return false;
+    }
+
+    let target = prev.split_off(split_at);
+    let start = text_source_start(preceding, target.chars().count())
+        .unwrap_or(prev_span.end - target.len());
+    let target_span = Span::new(start, prev_span.end);
+    prev_span.end = target_span.start;
+    if prev.is_empty() {
+        items.pop();
+    }
+    items.push(Inline::Node(Node::new(
+        ASTNode::Ruby {
+            target: vec![Node::new(ASTNode::Text(target), target_span)],
+            text: ruby_text(text),
+        },
+        Span::new(target_span.start, end),
+    )));
+
+    true
+}
+
+/// Resolves the backslash escapes and character references of the ruby text.
+fn ruby_text(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(|t| match t.token_type {
+            TokenType::Text => decode_entities(&t.raw),
+            TokenType::EscapedChar => t.raw[1..].to_string(),
+            _ => raw_text(std::slice::from_ref(t)),
+        })
+        .collect()
+}
+
+/// Finds the offset in the input of the last `count` decoded characters of the `Text` tokens
+/// `tokens` end with.
+fn text_source_start(tokens: &[Token], count: usize) -> Option<usize> {
+    tokens
+        .iter()
+        .rev()
+        .take_while(|t| t.token_type == TokenType::Text)
+        .flat_map(|t| {
+            decode_entities_with_offsets(&t.raw)
+                .into_iter()
+                .rev()
+                .map(|(offset, _)| t.span.start + offset)
+        })
+        .nth(count.checked_sub(1)?)
+}
+
+fn process_emphasis(items: &mut Vec<Inline>, bottom: usize) {
+    // 対応する開始が見つからなかった終了の種類ごとに、次に開始を探す範囲の下限を記録する
+    let mut openers_bottom = HashMap::new();
+    let mut closer = bottom;
+    while closer < items.len() {
+        let Inline::Delimiter {
+            char,
+            count,
+            length: closer_length,
+            can_open: closer_can_open,
+            can_close: true,
+            span,
+        } = items[closer]
+        else {
+            closer += 1;
+
+            continue;
+        };
+
+        let key = (char, closer_can_open, closer_length % 3);
+        let lower = openers_bottom.get(&key).copied().unwrap_or(bottom);
+        let Some(opener) = (lower..closer)
+            .rev()
+            .find(|&i| match items[i] {
+                Inline::Delimiter {
+                    char: c,
+                    length,
+                    can_open: true,
+                    can_close,
+                    ..
+                } if c == char => {
+                    // 開始と終了の両方になれる場合、長さの和が3の倍数なら対応させない
+                    !((can_close || closer_can_open)
+                        && (length + closer_length) % 3 == 0
+                        && (length % 3 != 0 || closer_length % 3 != 0))
+                }
+                _ => false,
+            })
+            .filter(|&opener| nesting_depth(&items[opener + 1..closer]) < MAX_NESTING_DEPTH)
+        else {
+            openers_bottom.insert(key, closer);
+            // 開始にもなれない終了はもう使われないため文字列にする
+            if !closer_can_open {
+                items[closer] = Inline::Node(Node::new(
+                    ASTNode::Text(char.to_string().repeat(count)),
+                    span,
+                ));
+            }
+            closer += 1;
+
+            continue;
+        };
+
+        let used = if delimiter_count(&items[opener]) >= 2 && delimiter_count(&items[closer]) >= 2 {
+            2
+        } else {
+            1
+        };
+        if let Inline::Delimiter { count, span, .. } = &mut items[opener] {
+            *count -= used;
+            span.end -= used;
+        }
+        if let Inline::Delimiter { count, span, .. } = &mut items[closer] {
+            *count -= used;
+            span.start += used;
+        }
+        let span = Span::new(
+            delimiter_span(&items[opener]).end,
+            delimiter_span(&items[closer]).start,
+        );
+
+        let children = into_nodes(items.drain(opener + 1..closer).collect());
+        items.insert(
+            opener + 1,
+            Inline::Node(Node::new(
+                if used == 2 {
+                    ASTNode::Bold(children)
+                } else {
+                    ASTNode::Italic(children)
+                },
+                span,
+            )),
+        );
+
+        // 開始と終了の間の要素はまとめられたため、下限をその手前に戻す
+        for lower in openers_bottom.values_mut() {
+            *lower = (*lower).min(opener);
+        }
+        closer = opener + 2;
+        if delimiter_count(&items[opener]) == 0 {
+            items.remove(opener);
+            closer -= 1;
+        }
+        if delimiter_count(&items[closer]) == 0 {
+            items.remove(closer);
+        }
+    }
+}
+
+/// Turns line endings into spaces and strips one space from both ends if both are present.
+fn code_span_text(raw: &str) -> String {
+    let text = raw.replace('\n', " ");
+    if text.len() >= 2
+        && text.starts_with(' ')
+        && text.ends_with(' ')
+        && text.bytes().any(|b| b != b' ')
+    {
+        text[1..text.len() - 1].to_string()
+    } else {
+        text
+    }
+}
+
+/// Nesting depth of the emphasis, links, images and ruby among `items`.
+fn nesting_depth(items: &[Inline]) -> usize {
+    items
+        .iter()
+        .map(|item| match item {
+            Inline::Node(node) => node_depth(node),
+            _ => 0,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn node_depth(node: &Node) -> usize {
+    match &node.kind {
+        ASTNode::Bold(children)
+        | ASTNode::Italic(children)
+        | ASTNode::Link { children, .. }
+        | ASTNode::Image { alt: children, .. }
+        | ASTNode::Ruby {
+            target: children, ..
+        } => 1 + children.iter().map(node_depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn delimiter_count(item: &Inline) -> usize {
+    match item {
+        Inline::Delimiter { count, .. } => *count,
+        _ => 0,
+    }
+}
+
+fn delimiter_span(item: &Inline) -> Span {
+    match item {
+        Inline::Delimiter { span, .. } => *span,
+        _ => Span::default(),
+    }
+}
+
+fn push_text(items: &mut Vec<Inline>, text: &str, span: Span) {
+    if let Some(Inline::Node(Node {
+        kind: ASTNode::Text(prev),
+        span: prev_span,
+    })) = items.last_mut()
+    {
+        prev.push_str(text);
+        prev_span.end = span.end;
+    } else {
+        items.push(Inline::Node(Node::new(
+            ASTNode::Text(text.to_string()),
+            span,
+        )));
+    }
+}
+
+fn into_nodes(items: Vec<Inline>) -> Vec<Node> {
+    let mut nodes: Vec<Node> = vec![];
+    for item in items {
+        let node = match item {
+            Inline::Node(node) => node,
+            Inline::Delimiter {
+                char, count, span, ..
+            } => Node::new(ASTNode::Text(char.to_string().repeat(count)), span),
+            Inline::LinkOpening {
+                is_image: true,
+                span,
+                ..
+            } => Node::new(ASTNode::Text("![".to_string()), span),
+            Inline::LinkOpening { span, .. } => Node::new(ASTNode::Text("[".to_string()), span),
+            Inline::RubyTargetOpening(span) => Node::new(ASTNode::Text("｜".to_string()), span),
+        };
+
+        match (nodes.last_mut(), node) {
+            (
+                Some(Node {
+                    kind: ASTNode::Text(prev),
+                    span: prev_span,
+                }),
+                Node {
+                    kind: ASTNode::Text(text),
+                    span,
+                },
+            ) => {
+                prev.push_str(&text);
+                prev_span.end = span.end;
+            }
+            (_, node) => nodes.push(node),
+        }
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{ASTNode, Node},
+        parse,
+        span::Span,
+    };
+
+    fn parse_paragraph(input: &str) -> Vec<Node> {
+        match parse(input).children.pop().map(|node| node.kind) {
+            Some(ASTNode::Paragraph(children)) => children,
+            node => panic!("expected paragraph, got {:?}", node),
+        }
+    }
+
+    fn node(kind: ASTNode, start: usize, end: usize) -> Node {
+        Node::new(kind, Span::new(start, end))
+    }
+
+    fn text(text: &str, start: usize, end: usize) -> Node {
+        node(ASTNode::Text(text.to_string()), start, end)
+    }
+
+    #[test]
+    fn parse_emphasis() {
+        // 強調
+        assert_eq!(
+            parse_paragraph("***Hello,** World!*"),
+            vec![node(
+                ASTNode::Italic(vec![
+                    node(ASTNode::Bold(vec![text("Hello,", 3, 9)]), 1, 11),
+                    text(" World!", 11, 18),
+                ]),
+                0,
+                19
+            )]
+        );
+
+        // 対応しない終了は文字列になり、後の強調は通常どおり対応する
+        assert_eq!(
+            parse_paragraph("*a_ *b_ *c*"),
+            vec![
+                text("*a_ *b_ ", 0, 8),
+                node(ASTNode::Italic(vec![text("c", 9, 10)]), 8, 11),
+            ]
+        );
+        assert_eq!(
+            parse_paragraph("aaa*bbb*ccc"),
+            vec![
+                text("aaa", 0, 3),
+                node(ASTNode::Italic(vec![text("bbb", 4, 7)]), 3, 8),
+                text("ccc", 8, 11),
+            ]
+        );
+
+        // 3の倍数の規則
+        assert_eq!(
+            parse_paragraph("*foo**bar**baz*"),
+            vec![node(
+                ASTNode::Italic(vec![
+                    text("foo", 1, 4),
+                    node(ASTNode::Bold(vec![text("bar", 6, 9)]), 4, 11),
+                    text("baz", 11, 14),
+                ]),
+                0,
+                15
+            )]
+        );
+
+        // 余った区切り文字はテキストになる
+        assert_eq!(
+            parse_paragraph("**foo*"),
+            vec![
+                text("*", 0, 1),
+                node(ASTNode::Italic(vec![text("foo", 2, 5)]), 1, 6),
+            ]
+        );
+
+        // 単語の途中の `_`
+        assert_eq!(
+            parse_paragraph("foo_bar_ _baz_"),
+            vec![
+                text("foo_bar_ ", 0, 9),
+                node(ASTNode::Italic(vec![text("baz", 10, 13)]), 9, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_code_span() {
+        // コードスパン
+        assert_eq!(
+            parse_paragraph("`Hello, World!` ``a`b``"),
+            vec![
+                node(ASTNode::Code("Hello, World!".to_string()), 0, 15),
+                text(" ", 15, 16),
+                node(ASTNode::Code("a`b".to_string()), 16, 23),
+            ]
+        );
+
+        // 改行は空白になり、両端の空白は1つだけ取り除かれる
+        assert_eq!(
+            parse_paragraph("``  foo\n*bar*《baz》 ``"),
+            vec![node(ASTNode::Code(" foo *bar*《baz》".to_string()), 0, 25)]
+        );
+
+        // 空白だけの場合は取り除かない。全角空白は空白として扱わない
+        assert_eq!(
+            parse_paragraph("`  ` ` 　 `"),
+            vec![
+                node(ASTNode::Code("  ".to_string()), 0, 4),
+                text(" ", 4, 5),
+                node(ASTNode::Code("　".to_string()), 5, 12),
+            ]
+        );
+
+        // 長さが一致しない
+        assert_eq!(parse_paragraph("```foo``"), vec![text("```foo``", 0, 8)]);
+    }
+
+    #[test]
+    fn parse_link() {
+        // リンク
+        assert_eq!(
+            parse_paragraph("[Hello, *World!*](https://example.com \"title\")"),
+            vec![node(
+                ASTNode::Link {
+                    href: "https://example.com".to_string(),
+                    title: Some("title".to_string()),
+                    children: vec![
+                        text("Hello, ", 1, 8),
+                        node(ASTNode::Italic(vec![text("World!", 9, 15)]), 8, 16),
+                    ],
+                },
+                0,
+                46
+            )]
+        );
+        assert_eq!(
+            parse_paragraph("<https://example.com>"),
+            vec![node(
+                ASTNode::Link {
+                    href: "https://example.com".to_string(),
+                    title: None,
+                    children: vec![text("https://example.com", 1, 20)],
+                },
+                0,
+                21
+            )]
+        );
+
+        // リンク先はパーセントエンコードする
+        assert_eq!(
+            parse_paragraph("[a](/ä\\[%41%) <http://b/\\[>"),
+            vec![
+                node(
+                    ASTNode::Link {
+                        href: "/%C3%A4%5B%41%25".to_string(),
+                        title: None,
+                        children: vec![text("a", 1, 2)],
+                    },
+                    0,
+                    14
+                ),
+                text(" ", 14, 15),
+                node(
+                    ASTNode::Link {
+                        href: "http://b/%5C%5B".to_string(),
+                        title: None,
+                        children: vec![text("http://b/\\[", 16, 27)],
+                    },
+                    15,
+                    28
+                ),
+            ]
+        );
+
+        // `<>` で囲まれたリンク先とリンク参照定義
+        assert_eq!(
+            parse_paragraph("[a](<b)c> \"t\\\"&amp;\") [d]\n\n[d]: <my url> 'e'"),
+            vec![
+                node(
+                    ASTNode::Link {
+                        href: "b)c".to_string(),
+                        title: Some("t\"&".to_string()),
+                        children: vec![text("a", 1, 2)],
+                    },
+                    0,
+                    21
+                ),
+                text(" ", 21, 22),
+                node(
+                    ASTNode::Link {
+                        href: "my%20url".to_string(),
+                        title: Some("e".to_string()),
+                        children: vec![text("d", 23, 24)],
+                    },
+                    22,
+                    25
+                ),
+            ]
+        );
+
+        // 括弧の入れ子は32段まで
+        let dest = format!("{}{}", "(".repeat(32), ")".repeat(32));
+        assert_eq!(
+            parse_paragraph(&format!("[a]({})", dest)),
+            vec![node(
+                ASTNode::Link {
+                    href: dest.clone(),
+                    title: None,
+                    children: vec![text("a", 1, 2)],
+                },
+                0,
+                69
+            )]
+        );
+        let input = format!("[a]({}{})", "(".repeat(33), ")".repeat(33));
+        assert_eq!(parse_paragraph(&input), vec![text(&input, 0, 71)]);
+    }
+
+    #[test]
+    fn parse_reference_link() {
+        // 参照リンク
+        let link = |children, start, end| {
+            node(
+                ASTNode::Link {
+                    href: "/url".to_string(),
+                    title: Some("title".to_string()),
+                    children,
+                },
+                start,
+                end,
+            )
+        };
+        assert_eq!(
+            parse_paragraph("[a][B  C] [b c][] [B C]\n\n[b c]: /url \"title\"\n[B C]: /second"),
+            vec![
+                link(vec![text("a", 1, 2)], 0, 9),
+                text(" ", 9, 10),
+                link(vec![text("b c", 11, 14)], 10, 17),
+                text(" ", 17, 18),
+                link(vec![text("B C", 19, 22)], 18, 23),
+            ]
+        );
+
+        // ラベルが定義されていない場合
+        assert_eq!(
+            parse_paragraph("[foo][bar][baz]\n\n[baz]: /url \"title\""),
+            vec![text("[foo]", 0, 5), link(vec![text("bar", 6, 9)], 5, 15),]
+        );
+
+        // 参照先のないラベルの中身はインライン要素として扱う
+        assert_eq!(
+            parse_paragraph("[x][*b*] [a][b\\*c]"),
+            vec![
+                text("[x][", 0, 4),
+                node(ASTNode::Italic(vec![text("b", 5, 6)]), 4, 7),
+                text("] [a][b*c]", 7, 18),
+            ]
+        );
+
+        // ラベルは999文字まで
+        let label = "あ".repeat(999);
+        assert_eq!(
+            parse_paragraph(&format!("[{label}]\n\n[{label}]: /url \"title\"")),
+            vec![link(vec![text(&label, 1, 2998)], 0, 2999)]
+        );
+        let label = "a".repeat(1000);
+        assert_eq!(
+            parse_paragraph(&format!("[{label}]\n\n[{label}]: /url \"title\"")),
+            vec![text(&format!("[{label}]"), 0, 1002)]
+        );
+        // リンクテキストの長さは制限しない
+        assert_eq!(
+            parse_paragraph(&format!("[{label}][b]\n\n[b]: /url \"title\"")),
+            vec![link(vec![text(&label, 1, 1001)], 0, 1005)]
+        );
+    }
+
+    #[test]
+    fn parse_image() {
+        // 画像
+        assert_eq!(
+            parse_paragraph("![Hello, *World!*](https://example.com)"),
+            vec![node(
+                ASTNode::Image {
+                    href: "https://example.com".to_string(),
+                    title: None,
+                    alt: vec![
+                        text("Hello, ", 2, 9),
+                        node(ASTNode::Italic(vec![text("World!", 10, 16)]), 9, 17),
+                    ],
+                },
+                0,
+                39
+            )]
+        );
+
+        // リンクの中の画像
+        assert_eq!(
+            parse_paragraph("[![a](b)](c)"),
+            vec![node(
+                ASTNode::Link {
+                    href: "c".to_string(),
+                    title: None,
+                    children: vec![node(
+                        ASTNode::Image {
+                            href: "b".to_string(),
+                            title: None,
+                            alt: vec![text("a", 3, 4)],
+                        },
+                        1,
+                        8
+                    )],
+                },
+                0,
+                12
+            )]
+        );
+
+        // リンクの中にリンクは置けない
+        assert_eq!(
+            parse_paragraph("[a [b](c)](d)"),
+            vec![
+                text("[a ", 0, 3),
+                node(
+                    ASTNode::Link {
+                        href: "c".to_string(),
+                        title: None,
+                        children: vec![text("b", 4, 5)],
+                    },
+                    3,
+                    9
+                ),
+                text("](d)", 9, 13),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_backslash_escape() {
+        // バックスラッシュエスケープ
+        assert_eq!(
+            parse_paragraph("\\*not emphasized* \\&ouml; \\[foo](/bar \"a\")"),
+            vec![text("*not emphasized* &ouml; [foo](/bar \"a\")", 0, 42)]
+        );
+        assert_eq!(
+            parse_paragraph("[foo](/bar\\* \"ti\\*tle\")"),
+            vec![node(
+                ASTNode::Link {
+                    href: "/bar*".to_string(),
+                    title: Some("ti*tle".to_string()),
+                    children: vec![text("foo", 1, 4)],
+                },
+                0,
+                23
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_entity() {
+        // 文字参照
+        assert_eq!(
+            parse_paragraph("&copy;&#12354; `&amp;` [&lt;](/&ouml; \"&#x3042;\")"),
+            vec![
+                text("©あ ", 0, 15),
+                node(ASTNode::Code("&amp;".to_string()), 15, 22),
+                text(" ", 22, 23),
+                node(
+                    ASTNode::Link {
+                        href: "/%C3%B6".to_string(),
+                        title: Some("あ".to_string()),
+                        children: vec![text("<", 24, 28)],
+                    },
+                    23,
+                    49
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_ruby() {
+        // ルビ
+        assert_eq!(
+            parse_paragraph("取捨｜選択《せんたく》"),
+            vec![
+                text("取捨", 0, 6),
+                node(
+                    ASTNode::Ruby {
+                        target: vec![text("選択", 9, 15)],
+                        text: "せんたく".to_string(),
+                    },
+                    6,
+                    33
+                ),
+            ]
+        );
+        assert_eq!(
+            parse_paragraph("僕は抽斗《ひきだし》を開けた。"),
+            vec![
+                text("僕は", 0, 6),
+                node(
+                    ASTNode::Ruby {
+                        target: vec![text("抽斗", 6, 12)],
+                        text: "ひきだし".to_string(),
+                    },
+                    6,
+                    30
+                ),
+                text("を開けた。", 30, 45),
+            ]
+        );
+
+        // 長音記号を含むカタカナ
+        assert_eq!(
+            parse_paragraph("熱いコーヒー《coffee》"),
+            vec![
+                text("熱い", 0, 6),
+                node(
+                    ASTNode::Ruby {
+                        target: vec![text("コーヒー", 6, 18)],
+                        text: "coffee".to_string(),
+                    },
+                    6,
+                    30
+                ),
+            ]
+        );
+
+        assert_eq!(
+            parse_paragraph("「時々」 時々《ときどき》"),
+            vec![
+                text("「時々」 ", 0, 13),
+                node(
+                    ASTNode::Ruby {
+                        target: vec![text("時々", 13, 19)],
+                        text: "ときどき".to_string(),
+                    },
+                    13,
+                    37
+                ),
+            ]
+        );
+
+        // リンクテキストの中のルビ
+        assert_eq!(
+            parse_paragraph("｜a [b《c》](d)"),
+            vec![
+                text("｜a ", 0, 5),
+                node(
+                    ASTNode::Link {
+                        href: "d".to_string(),
+                        title: None,
+                        children: vec![node(
+                            ASTNode::Ruby {
+                                target: vec![text("b", 6, 7)],
+                                text: "c".to_string(),
+                            },
+                            6,
+                            14
+                        )],
+                    },
+                    5,
+                    18
+                ),
+            ]
+        );
+
+        // 文字参照
+        assert_eq!(
+            parse_paragraph("漢&#23383;《a&lt;b》"),
+            vec![node(
+                ASTNode::Ruby {
+                    target: vec![text("漢字", 0, 11)],
+                    text: "a<b".to_string(),
+                },
+                0,
+                23
+            )]
+        );
+
+        // バックスラッシュエスケープ
+        assert_eq!(
+            parse_paragraph("漢字《a\\》b》"),
+            vec![node(
+                ASTNode::Ruby {
+                    target: vec![text("漢字", 0, 6)],
+                    text: "a》b".to_string(),
+                },
+                0,
+                18
+            )]
+        );
+    }
+
+    #[test]
+    fn parse_unmatched_opening() {
+        // 対応する閉じ記号がない場合は文字列として扱う
+        assert_eq!(
+            parse_paragraph("[Hello, World!"),
+            vec![text("[Hello, World!", 0, 14)]
+        );
+        assert_eq!(
+            parse_paragraph("[Hello,] World!"),
+            vec![text("[Hello,] World!", 0, 15)]
+        );
+        assert_eq!(
+            parse_paragraph("Hello, 《World!"),
+            vec![text("Hello, 《World!", 0, 16)]
+        );
+        assert_eq!(
+            parse_paragraph("｜Hello, World!"),
+            vec![text("｜Hello, World!", 0, 16)]
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use crate::ast::{plain_text, ASTNode, Document, HeadingLevel, ListItem, ListType, Node};
+
+/// Renders a document as CommonMark-compatible HTML.
+pub fn render(document: &Document) -> String {
+    let mut output = String::new();
+    render_blocks(&document.children, &mut output);
+
+    output
+}
+
+fn render_blocks(nodes: &[Node], output: &mut String) {
+    for node in nodes {
+        render_block(&node.kind, output);
+    }
+}
+
+fn render_block(node: &ASTNode, output: &mut String) {
+    match node {
+        ASTNode::Heading { level, children } => {
+            let tag = heading_tag(level);
+            output.push_str(&format!("<{}>", tag));
+            render_inlines(children, output);
+            output.push_str(&format!("</{}>\n", tag));
+        }
+        ASTNode::Paragraph(children) => {
+            output.push_str("<p>");
+            render_inlines(children, output);
+            output.push_str("</p>\n");
+        }
+        ASTNode::BlockQuote(children) => {
+            output.push_str("<blockquote>\n");
+            render_blocks(children, output);
+            output.push_str("</blockquote>\n");
+        }
+        ASTNode::CodeBlock { language, text, .. } => {
+            output.push_str("<pre><code");
+            if !language.is_empty() {
+                output.push_str(&format!(" class=\"language-{}\"", escape(language)));
+            }
+            output.push('>');
+            output.push_str(&escape(text));
+            output.push_str("</code></pre>\n");
+        }
+        ASTNode::List {
+            list_type,
+            start,
+            tight,
+            children,
+            ..
+        } => {
+            match (list_type, start) {
+                (ListType::Ordered, Some(start)) if *start != 1 => {
+                    output.push_str(&format!("<ol start=\"{}\">\n", start))
+                }
+                (ListType::Ordered, _) => output.push_str("<ol>\n"),
+                _ => output.push_str("<ul>\n"),
+            }
+            for item in children {
+                render_list_item(item, *tight, output);
+            }
+            match list_type {
+                ListType::Ordered => output.push_str("</ol>\n"),
+                _ => output.push_str("</ul>\n"),
+            }
+        }
+        ASTNode::HorizontalRule => output.push_str("<hr />\n"),
+        ASTNode::HtmlBlock(html) => {
+            output.push_str(html);
+            output.push('\n');
+        }
+        node => {
+            output.push_str("<p>");
+            render_inline(node, output);
+            output.push_str("</p>\n");
+        }
+    }
+}
+
+fn render_list_item(item: &ListItem, tight: bool, output: &mut String) {
+    let mut checkbox = match item.checked {
+        Some(true) => "<input type=\"checkbox\" checked=\"\" disabled=\"\" /> ",
+        Some(false) => "<input type=\"checkbox\" disabled=\"\" /> ",
+        None => "",
+    };
+
+    output.push_str("<li>");
+    for node in &item.children {
+        match &node.kind {
+            // 密なリストの段落は `<p>` で囲まない
+            ASTNode::Paragraph(children) if tight => {
+                output.push_str(checkbox);
+                render_inlines(children, output);
+            }
+            ASTNode::Paragraph(children) => {
+                new_line(output);
+                output.push_str("<p>");
+                output.push_str(checkbox);
+                render_inlines(children, output);
+                output.push_str("</p>\n");
+            }
+            kind => {
+                output.push_str(checkbox.trim_end());
+                new_line(output);
+                render_block(kind, output);
+            }
+        }
+        checkbox = "";
+    }
+    output.push_str(checkbox.trim_end());
+    output.push_str("</li>\n");
+}
+
+fn new_line(output: &mut String) {
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+fn render_inlines(nodes: &[Node], output: &mut String) {
+    for node in nodes {
+        render_inline(&node.kind, output);
+    }
+}
+
+fn render_inline(node: &ASTNode, output: &mut String) {
+    match node {
+        ASTNode::Bold(children) => {
+            output.push_str("<strong>");
+            render_inlines(children, output);
+            output.push_str("</strong>");
+        }
+        ASTNode::Italic(children) => {
+            output.push_str("<em>");
+            render_inlines(children, output);
+            output.push_str("</em>");
+        }
+        ASTNode::Code(code) => {
+            output.push_str("<code>");
+            output.push_str(&escape(code));
+            output.push_str("</code>");
+        }
+        ASTNode::Link {
+            href,
+            title,
+            children,
+        } => {
+            output.push_str(&format!("<a href=\"{}\"", escape(href)));
+            if let Some(title) = title {
+                output.push_str(&format!(" title=\"{}\"", escape(title)));
+            }
+            output.push('>');
+            render_inlines(children, output);
+            output.push_str("</a>");
+        }
+        ASTNode::Image { href, title, alt } => {
+            output.push_str(&format!(
+                "<img src=\"{}\" alt=\"{}\"",
+                escape(href),
+                escape(&plain_text(alt))
+            ));
+            if let Some(title) = title {
+                output.push_str(&format!(" title=\"{}\"", escape(title)));
+            }
+            output.push_str(" />");
+        }
+        ASTNode::Ruby { target, text } => {
+            output.push_str("<ruby>");
+            render_inlines(target, output);
+            output.push_str(&format!(
+                "<rp>(</rp><rt>{}</rt><rp>)</rp></ruby>",
+                escape(text)
+            ));
+        }
+        ASTNode::InlineHtml(html) => output.push_str(html),
+        ASTNode::HardBreak => output.push_str("<br />\n"),
+        ASTNode::SoftBreak => output.push('\n'),
+        ASTNode::Text(text) => output.push_str(&escape(text)),
+        node => render_block(node, output),
+    }
+}
+
+fn heading_tag(level: &HeadingLevel) -> &'static str {
+    match level {
+        HeadingLevel::H1 => "h1",
+        HeadingLevel::H2 => "h2",
+        HeadingLevel::H3 => "h3",
+        HeadingLevel::H4 => "h4",
+        HeadingLevel::H5 => "h5",
+        HeadingLevel::H6 => "h6",
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+    use crate::parse;
+
+    #[test]
+    fn render_block() {
+        // ブロック要素
+        let input = "# Hello\n\n> Hello, World!\n\n```rust\nfn main() {}\n```\n\n---";
+        assert_eq!(
+            render(&parse(input)),
+            "<h1>Hello</h1>\n<blockquote>\n<p>Hello, World!</p>\n</blockquote>\n<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n<hr />\n"
+        );
+    }
+
+    #[test]
+    fn render_html() {
+        // HTMLはそのまま出力する
+        let input = "<div>\n*Hello*\n</div>\n\n<b>World</b>!";
+        assert_eq!(
+            render(&parse(input)),
+            "<div>\n*Hello*\n</div>\n<p><b>World</b>!</p>\n"
+        );
+    }
+
+    #[test]
+    fn render_list() {
+        // リスト
+        let input = "1. Hello,\n2. World!\n\n- [ ] Hello,\n\n- [x] World!";
+        assert_eq!(
+            render(&parse(input)),
+            "<ol>\n<li>Hello,</li>\n<li>World!</li>\n</ol>\n<ul>\n<li>\n<p><input type=\"checkbox\" disabled=\"\" /> Hello,</p>\n</li>\n<li>\n<p><input type=\"checkbox\" checked=\"\" disabled=\"\" /> World!</p>\n</li>\n</ul>\n"
+        );
+
+        // 開始番号と入れ子の密なリスト
+        let input = "3) a\n   - [x] b\n   + c";
+        assert_eq!(
+            render(&parse(input)),
+            "<ol start=\"3\">\n<li>a\n<ul>\n<li><input type=\"checkbox\" checked=\"\" disabled=\"\" /> b</li>\n</ul>\n<ul>\n<li>c</li>\n</ul>\n</li>\n</ol>\n"
+        );
+
+        // チェックボックスは印のある項目にだけ付ける
+        let input = "- [ ] a\n- b";
+        assert_eq!(
+            render(&parse(input)),
+            "<ul>\n<li><input type=\"checkbox\" disabled=\"\" /> a</li>\n<li>b</li>\n</ul>\n"
+        );
+
+        // 2番目以降の項目と順序付きリストのチェックボックス
+        let input = "- a\n- [x] b\n\n1. [ ] c";
+        assert_eq!(
+            render(&parse(input)),
+            "<ul>\n<li>a</li>\n<li><input type=\"checkbox\" checked=\"\" disabled=\"\" /> b</li>\n</ul>\n<ol>\n<li><input type=\"checkbox\" disabled=\"\" /> c</li>\n</ol>\n"
+        );
+    }
+
+    #[test]
+    fn render_inline() {
+        // インライン要素
+        let input =
+            "**Hello**, *World* `<code>` [link](https://example.com \"title\") ![image](image.png)";
+        assert_eq!(
+            render(&parse(input)),
+            "<p><strong>Hello</strong>, <em>World</em> <code>&lt;code&gt;</code> <a href=\"https://example.com\" title=\"title\">link</a> <img src=\"image.png\" alt=\"image\" /></p>\n"
+        );
+
+        // 改行
+        assert_eq!(
+            render(&parse("Hello,  \nWorld \n!")),
+            "<p>Hello,<br />\nWorld\n!</p>\n"
+        );
+    }
+
+    #[test]
+    fn render_ruby() {
+        // ルビ
+        let input = "取捨｜選択《せんたく》";
+        assert_eq!(
+            render(&parse(input)),
+            "<p>取捨<ruby>選択<rp>(</rp><rt>せんたく</rt><rp>)</rp></ruby></p>\n"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+/// Byte range of a token or node in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// 1-based line and column. The column counts characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineColumn {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Converts byte offsets into line and column positions.
+///
+/// ```
+/// use caffe_paser::span::{LineColumn, LineIndex};
+///
+/// let input = "# 見出し\n本文";
+/// let index = LineIndex::new(input);
+/// assert_eq!(index.line_column(12), LineColumn { line: 2, column: 1 });
+/// assert_eq!(index.line_column(15), LineColumn { line: 2, column: 2 });
+/// ```
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    input: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(input: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { input, line_starts }
+    }
+
+    /// Offsets past the end of the input are clamped to it.
+    pub fn line_column(&self, offset: usize) -> LineColumn {
+        let mut offset = offset.min(self.input.len());
+        while !self.input.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+
+        LineColumn {
+            line,
+            column: self.input[line_start..offset].chars().count() + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineColumn, LineIndex, Span};
+
+    #[test]
+    fn line_column() {
+        let input = "abc\n\nあいう\n";
+        let index = LineIndex::new(input);
+
+        assert_eq!(index.line_column(0), LineColumn { line: 1, column: 1 });
+        assert_eq!(index.line_column(3), LineColumn { line: 1, column: 4 });
+        assert_eq!(index.line_column(4), LineColumn { line: 2, column: 1 });
+        assert_eq!(index.line_column(5), LineColumn { line: 3, column: 1 });
+        assert_eq!(index.line_column(8), LineColumn { line: 3, column: 2 });
+        // 文字の途中
+        assert_eq!(index.line_column(9), LineColumn { line: 3, column: 2 });
+        assert_eq!(index.line_column(15), LineColumn { line: 4, column: 1 });
+        assert_eq!(index.line_column(100), LineColumn { line: 4, column: 1 });
+    }
+
+    #[test]
+    fn span_len() {
+        assert_eq!(Span::new(3, 7).len(), 4);
+        assert!(Span::new(3, 3).is_empty());
+    }
+}
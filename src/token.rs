@@ -1,23 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+use crate::span::Span;
+
+/// An inline token of a leaf block, as produced before the tokens are parsed into [`ASTNode`]s.
+///
+/// [`ASTNode`]: crate::ast::ASTNode
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct Token {
-    pub(crate) token_type: TokenType,
-    pub(crate) raw: String,
+#[non_exhaustive]
+pub struct Token {
+    pub token_type: TokenType,
+    pub raw: String,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum TokenType {
-    ThemanticBreak,
-    ATXHeading(HeadingLevel),
-    SetextHeading(HeadingLevel),
-    IndentedCodeBlock,
-    FencedCodeBlock,
-    HTMLBlock,
+#[non_exhaustive]
+pub enum TokenType {
     LinkReferenceDefinition(String, String, Option<String>),
-    BlankLine,
-    BlockQuote,
-    BulletListItem,
-    OrderedListItem,
-    CheckListItem(bool),
     CodeSpan,
     Emphasis(DelimiterType),
     LinkTextOpening,
@@ -36,11 +35,15 @@ pub(crate) enum TokenType {
     RubyTextClosing,
     HardLineBreak,
     SoftLineBreak,
+    /// A backslash escaped punctuation character, such as `\*`.
+    EscapedChar,
     Text,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub(crate) enum HeadingLevel {
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum HeadingLevel {
     H1,
     H2,
     H3,
@@ -48,23 +51,31 @@ pub(crate) enum HeadingLevel {
     H5,
     H6,
 }
-impl From<u8> for HeadingLevel {
-    fn from(level: u8) -> Self {
+
+/// Fails with the given level unless it is between 1 and 6.
+impl TryFrom<u8> for HeadingLevel {
+    type Error = u8;
+
+    fn try_from(level: u8) -> Result<Self, Self::Error> {
         match level {
-            1 => HeadingLevel::H1,
-            2 => HeadingLevel::H2,
-            3 => HeadingLevel::H3,
-            4 => HeadingLevel::H4,
-            5 => HeadingLevel::H5,
-            6 => HeadingLevel::H6,
-            _ => unreachable!(),
+            1 => Ok(HeadingLevel::H1),
+            2 => Ok(HeadingLevel::H2),
+            3 => Ok(HeadingLevel::H3),
+            4 => Ok(HeadingLevel::H4),
+            5 => Ok(HeadingLevel::H5),
+            6 => Ok(HeadingLevel::H6),
+            _ => Err(level),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) enum DelimiterType {
+#[non_exhaustive]
+pub enum DelimiterType {
+    /// Can only close emphasis.
     RightFlanking,
+    /// Can only open emphasis.
     LeftFlanking,
+    /// Can both open and close emphasis.
     Both,
 }
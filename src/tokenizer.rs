@@ -1,67 +1,81 @@
+mod code;
 mod emphasis;
 mod html;
 mod link;
-mod list_item;
+
+use std::{iter::Peekable, str::CharIndices};
 
 use once_cell::sync::Lazy;
 use regex::Regex;
 
 use self::{
+    code::tokenize_code_span,
     emphasis::tokenize_emphasis,
     html::tokenize_html,
     link::{tokenize_inline_link_dest, tokenize_link_label, tokenize_link_reference_definition},
-    list_item::tokenize_list_item_type,
 };
-use crate::token::{Token, TokenType};
+use crate::{
+    span::Span,
+    token::{Token, TokenType},
+    ParseOptions,
+};
 
-static ABSOLUTE_URI_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^<[a-zA-Z][a-zA-Z0-9+.\-]{1,31}:[\w!\?/\+\-_~=;\.,\*&@#\$%\(\)'\[\]]+>").unwrap()
-});
+pub(crate) use self::html::{CLOSING_TAG, OPEN_TAG};
+
+static ABSOLUTE_URI_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^<[a-zA-Z][a-zA-Z0-9+.\-]{1,31}:[^\x00-\x20<>\x7f]*>").unwrap());
 static EMAIL_ADDRESS_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^<([a-zA-Z0-9.!#$%&'*+/=?^_`{|}~-]+@[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*)>$",).unwrap()
+    Regex::new(r"^<[a-zA-Z0-9.!#$%&'*+/=?^_`{|}~\-]+@[a-zA-Z0-9](?:[a-zA-Z0-9\-]{0,61}[a-zA-Z0-9])?(?:\.[a-zA-Z0-9](?:[a-zA-Z0-9\-]{0,61}[a-zA-Z0-9])?)*>").unwrap()
 });
 
-pub(crate) fn tokenize(input: &str) -> Vec<Token> {
+/// Character iterator that keeps track of the byte offset in the input.
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.char_indices().peekable(),
+            len: input.len(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.chars.peek().map(|(_, c)| c)
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map(|&(i, _)| i).unwrap_or(self.len)
+    }
+
+    fn advance_to(&mut self, offset: usize) {
+        while self.offset() < offset {
+            self.chars.next();
+        }
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.chars.next().map(|(_, c)| c)
+    }
+}
+
+pub(crate) fn tokenize(input: &str, options: &ParseOptions) -> Vec<Token> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut buffer = String::new();
+    // 閉じられていないリンクと画像の開始の数
+    let mut link_openers = 0usize;
 
-    let mut chars = input.chars().peekable();
+    let mut chars = Cursor::new(input);
     while let Some(&char) = chars.peek() {
+        let start = chars.offset();
         match char {
-            '#' if is_head_of_line(&tokens, buffer.clone()) => {
-                let mut level = 0;
-                while chars.peek() == Some(&'#') {
-                    level += 1;
-                    chars.next();
-                }
-
-                let raw = "#".repeat(level);
-                if chars.peek() != Some(&' ') {
-                    buffer.push_str(raw.as_str());
-                } else {
-                    tokens.push(Token {
-                        token_type: TokenType::ATXHeading((level as u8).into()),
-                        raw,
-                    });
-                }
-            }
-            ' ' if is_head_of_line(&tokens, buffer.clone()) => {
-                let mut count = 0;
-                while chars.peek() == Some(&' ') {
-                    count += 1;
-                    chars.next();
-                }
-
-                if count >= 4 {
-                    tokens.push(Token {
-                        token_type: TokenType::IndentedCodeBlock,
-                        raw: "    ".to_string(),
-                    });
-                    buffer.push_str(" ".repeat(count - 4).as_str());
-                } else {
-                    buffer.push_str(" ".repeat(count).as_str());
-                }
-            }
             ' ' => {
                 let mut count = 0;
                 while chars.peek() == Some(&' ') {
@@ -71,405 +85,231 @@ pub(crate) fn tokenize(input: &str) -> Vec<Token> {
 
                 if count >= 2 && chars.peek() == Some(&'\n') {
                     chars.next(); // skip '\n'
-                    if !buffer.is_empty() {
-                        tokens.push(Token {
-                            token_type: TokenType::Text,
-                            raw: buffer.clone(),
-                        });
-                        buffer.clear();
-                    }
+                    flush_buffer(&mut tokens, &mut buffer, start);
 
                     tokens.push(Token {
                         token_type: TokenType::HardLineBreak,
-                        raw: "  ".to_string(),
+                        raw: " ".repeat(count),
+                        span: Span::new(start, chars.offset()),
                     });
+                } else if chars.peek() == Some(&'\n') {
+                    // 行末の空白は取り除く
+                    flush_buffer(&mut tokens, &mut buffer, start);
                 } else {
                     buffer.push_str(" ".repeat(count).as_str());
                 }
             }
             '`' => {
-                let is_head_of_line = is_head_of_line(&tokens, buffer.clone());
                 let mut count = 0;
                 while chars.peek() == Some(&'`') {
                     count += 1;
                     chars.next();
                 }
 
-                if count >= 3 && is_head_of_line {
-                    tokens.push(Token {
-                        token_type: TokenType::FencedCodeBlock,
-                        raw: "`".repeat(count),
-                    });
-                } else {
-                    if !buffer.is_empty() {
-                        tokens.push(Token {
-                            token_type: TokenType::Text,
-                            raw: buffer.clone(),
-                        });
-                        buffer.clear();
+                // 閉じる記号がなければテキスト
+                match tokenize_code_span(&input[start..], count, start) {
+                    Some(code_tokens) => {
+                        flush_buffer(&mut tokens, &mut buffer, start);
+                        chars.advance_to(code_tokens.last().unwrap().span.end);
+                        tokens.extend(code_tokens);
                     }
-
-                    tokens.push(Token {
-                        token_type: TokenType::CodeSpan,
-                        raw: "`".repeat(count),
-                    });
-                }
-            }
-            '>' if is_head_of_line(&tokens, buffer.clone()) => {
-                let mut count = 0;
-                while chars.peek() == Some(&'>') {
-                    count += 1;
-                    chars.next();
+                    None => buffer.push_str(&"`".repeat(count)),
                 }
-
-                tokens.push(Token {
-                    token_type: TokenType::BlockQuote,
-                    raw: ">".repeat(count),
-                });
             }
             '<' => {
-                let mut sub_buffer = String::new();
-                while chars.peek() != Some(&'>')
-                    && chars.peek() != Some(&'\n')
-                    && chars.peek() != None
+                let rest = &input[start..];
+                if let Some(link) = ABSOLUTE_URI_REGEX
+                    .find(rest)
+                    .or_else(|| EMAIL_ADDRESS_REGEX.find(rest))
                 {
-                    sub_buffer.push(chars.next().unwrap());
-                }
-                if let Some(c) = chars.next() {
-                    sub_buffer.push(c);
-                }
-
-                if ABSOLUTE_URI_REGEX.is_match(&sub_buffer)
-                    || EMAIL_ADDRESS_REGEX.is_match(&sub_buffer)
-                {
-                    if !buffer.is_empty() {
-                        tokens.push(Token {
-                            token_type: TokenType::Text,
-                            raw: buffer.clone(),
-                        });
-                        buffer.clear();
-                    }
+                    flush_buffer(&mut tokens, &mut buffer, start);
+                    chars.advance_to(start + link.end());
 
                     tokens.push(Token {
                         token_type: TokenType::AutoLink,
-                        raw: sub_buffer,
+                        raw: link.as_str().to_string(),
+                        span: Span::new(start, start + link.end()),
                     });
 
                     continue;
                 }
 
-                tokenize_html(&mut tokens, &mut chars, &mut buffer, &mut sub_buffer);
-            }
-            '[' if is_head_of_line(&tokens, buffer.clone()) => {
-                tokenize_link_reference_definition(&mut tokens, &mut chars)
+                match tokenize_html(rest, start) {
+                    Some(token) => {
+                        flush_buffer(&mut tokens, &mut buffer, start);
+                        chars.advance_to(token.span.end);
+                        tokens.push(token);
+                    }
+                    None => {
+                        chars.next();
+                        buffer.push('<');
+                    }
+                }
             }
             '[' => {
                 chars.next(); // skip '['
-                if tokens
-                    .last()
-                    .map(|t| t.token_type == TokenType::LinkTextClosing)
-                    == Some(true)
-                {
-                    match tokenize_link_label(&mut chars) {
-                        Ok(label) => {
-                            tokens.extend(vec![
-                                Token {
-                                    token_type: TokenType::LinkLabelMatchOpening,
-                                    raw: "[".to_string(),
-                                },
-                                Token {
-                                    token_type: TokenType::Text,
-                                    raw: label,
-                                },
-                                Token {
-                                    token_type: TokenType::LinkLabelMatchClosing,
-                                    raw: "]".to_string(),
-                                },
-                            ]);
-                            chars.next(); // skip ']'
+                flush_buffer(&mut tokens, &mut buffer, start);
+
+                let saved = chars.clone();
+                // `[]`の直後のリンクラベルは参照リンクのラベルとして扱う
+                let label = match tokens.last().map(|t| &t.token_type) {
+                    Some(TokenType::LinkTextClosing | TokenType::LinkLabelMatchClosing) => {
+                        if chars.peek() == Some(&']') {
+                            Some(String::new())
+                        } else {
+                            tokenize_link_label(&mut chars)
                         }
-                        Err(ts) => tokens.extend(ts),
                     }
-                } else {
-                    tokens.push(Token {
-                        token_type: TokenType::LinkTextOpening,
-                        raw: "[".to_string(),
-                    });
+                    _ => None,
+                };
+                match label {
+                    Some(label) => {
+                        let label_end = chars.offset();
+                        tokens.push(Token {
+                            token_type: TokenType::LinkLabelMatchOpening,
+                            raw: "[".to_string(),
+                            span: Span::new(start, start + 1),
+                        });
+                        // 参照先が見つからなければリンクテキストになるため、中身も通常どおり分割する
+                        tokens.extend(tokenize(&label, options).into_iter().map(|mut t| {
+                            t.span = Span::new(t.span.start + start + 1, t.span.end + start + 1);
+                            t
+                        }));
+                        chars.next(); // skip ']'
+                        tokens.push(Token {
+                            token_type: TokenType::LinkLabelMatchClosing,
+                            raw: "]".to_string(),
+                            span: Span::new(label_end, chars.offset()),
+                        });
+                    }
+                    None => {
+                        chars = saved;
+                        link_openers += 1;
+                        tokens.push(Token {
+                            token_type: TokenType::LinkTextOpening,
+                            raw: "[".to_string(),
+                            span: Span::new(start, start + 1),
+                        });
+                    }
                 }
             }
             '!' => {
                 chars.next(); // skip '!'
                 if chars.peek() == Some(&'[') {
                     chars.next(); // skip '['
+                    flush_buffer(&mut tokens, &mut buffer, start);
+
+                    link_openers += 1;
                     tokens.push(Token {
                         token_type: TokenType::ImageTextOpening,
                         raw: "![".to_string(),
+                        span: Span::new(start, chars.offset()),
                     });
                 } else {
                     buffer.push('!');
                 }
             }
             ']' => {
-                if !buffer.is_empty() {
-                    tokens.push(Token {
-                        token_type: TokenType::Text,
-                        raw: buffer.clone(),
-                    });
-                    buffer.clear();
-                }
+                flush_buffer(&mut tokens, &mut buffer, start);
 
                 chars.next(); // skip ']'
+                let has_opener = link_openers > 0;
+                link_openers = link_openers.saturating_sub(1);
                 tokens.push(Token {
                     token_type: TokenType::LinkTextClosing,
                     raw: "]".to_string(),
+                    span: Span::new(start, start + 1),
                 });
 
-                if chars.peek() == Some(&'(') {
-                    chars.next(); // skip '('
-
+                // 対応する`[`がない場合はリンクにならないため、`(`以降は文字列として扱う
+                if has_opener && chars.peek() == Some(&'(') {
+                    let saved = chars.clone();
                     match tokenize_inline_link_dest(&mut chars) {
-                        Ok(ts) => tokens.extend(ts),
-                        Err(t) => tokens.extend(tokenize(t.as_str())),
+                        Some(ts) => tokens.extend(ts),
+                        None => chars = saved,
                     }
                 }
             }
-            '(' => {
-                if buffer.is_empty()
-                    && tokens
-                        .last()
-                        .map(|t| t.token_type == TokenType::LinkTextClosing)
-                        == Some(true)
-                {
-                    chars.next(); // skip '('
-
-                    match tokenize_inline_link_dest(&mut chars) {
-                        Ok(ts) => tokens.extend(ts),
-                        Err(t) => tokens.extend(tokenize(t.as_str())),
-                    }
-                } else {
-                    buffer.push('(');
-                    chars.next();
-                }
-            }
-            '-' => {
-                let is_head_of_line = is_head_of_line(&tokens, buffer.clone());
+            '*' | '_' => {
                 let mut sub_buffer = String::new();
-                while chars.peek() == Some(&'-') || chars.peek() == Some(&' ') {
+                while chars.peek() == Some(&char) {
                     sub_buffer.push(chars.next().unwrap());
                 }
 
-                if !buffer.is_empty() && buffer.chars().any(|c| c != ' ') || !is_head_of_line {
-                    buffer.push_str(&sub_buffer);
-                } else if sub_buffer.chars().filter(|&c| c == '-').count() >= 3
-                    && (chars.peek() == Some(&'\n') || chars.peek() == None)
-                {
-                    tokens.push(Token {
-                        token_type: TokenType::ThemanticBreak,
-                        raw: sub_buffer,
-                    });
-                } else if chars.peek() == Some(&'\n') || chars.peek() == None {
-                    tokens.push(Token {
-                        token_type: TokenType::SetextHeading(2.into()),
-                        raw: sub_buffer,
-                    });
-                } else if sub_buffer.chars().last() == Some(' ')
-                    && sub_buffer.chars().filter(|&c| c == '-').count() == 1
-                {
-                    tokens.push(tokenize_list_item_type(&mut chars, sub_buffer));
-                } else {
-                    buffer.push_str(&sub_buffer);
-                }
+                tokenize_emphasis(
+                    &mut tokens,
+                    chars.peek(),
+                    &mut buffer,
+                    &mut sub_buffer,
+                    start,
+                );
             }
-            '*' => {
-                let is_head_of_line = is_head_of_line(&tokens, buffer.clone());
-                let mut sub_buffer = String::new();
-                while chars.peek() == Some(&'*') || chars.peek() == Some(&' ') {
-                    sub_buffer.push(chars.next().unwrap());
-                }
-
-                if is_head_of_line
-                    && sub_buffer.chars().filter(|&c| c == '*').count() >= 3
-                    && (chars.peek() == Some(&'\n') || chars.peek() == None)
-                {
-                    tokens.push(Token {
-                        token_type: TokenType::ThemanticBreak,
-                        raw: sub_buffer,
-                    });
-                } else if is_head_of_line
-                    && sub_buffer.chars().last() == Some(' ')
-                    && sub_buffer.chars().filter(|&c| c == '*').count() == 1
-                {
-                    tokens.push(tokenize_list_item_type(&mut chars, sub_buffer));
-                } else {
-                    tokenize_emphasis(&mut tokens, chars.peek(), &mut buffer, &mut sub_buffer);
-                }
-            }
-            '_' => {
-                let is_head_of_line = is_head_of_line(&tokens, buffer.clone());
-                let mut sub_buffer = String::new();
-                while chars.peek() == Some(&'_') || chars.peek() == Some(&' ') {
-                    sub_buffer.push(chars.next().unwrap());
-                }
-
-                if is_head_of_line && sub_buffer.chars().filter(|&c| c == '_').count() >= 3 {
-                    tokens.push(Token {
-                        token_type: TokenType::ThemanticBreak,
-                        raw: sub_buffer,
-                    });
-                } else {
-                    tokenize_emphasis(&mut tokens, chars.peek(), &mut buffer, &mut sub_buffer);
-                }
-            }
-            '+' => {
-                let is_head_of_line = is_head_of_line(&tokens, buffer.clone());
-                let mut sub_buffer = String::new();
-                while chars.peek() == Some(&'+') || chars.peek() == Some(&' ') {
-                    sub_buffer.push(chars.next().unwrap());
-                }
-
-                if is_head_of_line
-                    && sub_buffer.chars().last() == Some(' ')
-                    && sub_buffer.chars().filter(|&c| c == '+').count() == 1
-                {
-                    tokens.push(tokenize_list_item_type(&mut chars, sub_buffer));
-                } else {
-                    buffer.push_str(&sub_buffer);
-                }
-            }
-            '=' => {
-                let is_head_of_line = is_head_of_line(&tokens, buffer.clone());
-                let mut sub_buffer = String::new();
-                while chars.peek() == Some(&'=') || chars.peek() == Some(&' ') {
-                    sub_buffer.push(chars.next().unwrap());
-                }
-
-                if is_head_of_line && (chars.peek() == Some(&'\n') || chars.peek() == None) {
-                    tokens.push(Token {
-                        token_type: TokenType::SetextHeading(1.into()),
-                        raw: sub_buffer,
-                    });
-                } else {
-                    buffer.push_str(&sub_buffer);
-                }
-            }
-            '0'..='9' => {
-                if !is_head_of_line(&tokens, buffer.clone()) {
-                    buffer.push(char);
-                    chars.next();
-
-                    continue;
-                };
-
-                let mut sub_buffer = String::new();
-                let mut digits = 0;
-                while chars.peek().map(|c| c.is_ascii_digit()) == Some(true) && digits < 9 {
-                    sub_buffer.push(chars.next().unwrap());
-                    digits += 1;
-                }
-
-                let delimiter = if chars.peek() == Some(&'.') || chars.peek() == Some(&')') {
-                    chars.next().unwrap()
-                } else {
-                    buffer.push_str(&sub_buffer);
-
-                    continue;
-                };
-
-                if chars.peek() == Some(&' ') {
-                    chars.next(); // skip ' '
-                    tokens.push(Token {
-                        token_type: TokenType::OrderedListItem,
-                        raw: format!("{}{} ", sub_buffer, delimiter),
-                    });
-                } else {
-                    buffer.push_str(&sub_buffer);
-                    buffer.push(delimiter);
-                }
-            }
-            '｜' => {
-                if !buffer.is_empty() {
-                    tokens.push(Token {
-                        token_type: TokenType::Text,
-                        raw: buffer.clone(),
-                    });
-                    buffer.clear();
-                }
+            '｜' if options.ruby => {
+                flush_buffer(&mut tokens, &mut buffer, start);
 
+                chars.next(); // skip '｜'
                 tokens.push(Token {
                     token_type: TokenType::RubyTargetOpening,
                     raw: "｜".to_string(),
+                    span: Span::new(start, chars.offset()),
                 });
-                chars.next(); // skip '｜'
             }
-            '《' => {
-                if !buffer.is_empty() {
-                    tokens.push(Token {
-                        token_type: TokenType::Text,
-                        raw: buffer.clone(),
-                    });
-                    buffer.clear();
-                }
+            '《' if options.ruby => {
+                flush_buffer(&mut tokens, &mut buffer, start);
 
+                chars.next(); // skip '《'
                 tokens.push(Token {
                     token_type: TokenType::RubyTextOpening,
                     raw: "《".to_string(),
+                    span: Span::new(start, chars.offset()),
                 });
-                chars.next(); // skip '《'
             }
-            '》' => {
-                if !buffer.is_empty() {
-                    tokens.push(Token {
-                        token_type: TokenType::Text,
-                        raw: buffer.clone(),
-                    });
-                    buffer.clear();
-                }
+            '》' if options.ruby => {
+                flush_buffer(&mut tokens, &mut buffer, start);
 
+                chars.next(); // skip '》'
                 tokens.push(Token {
                     token_type: TokenType::RubyTextClosing,
                     raw: "》".to_string(),
+                    span: Span::new(start, chars.offset()),
                 });
-                chars.next(); // skip '》'
             }
             '\\' => {
                 chars.next(); // skip '\\'
-                if chars.peek() == Some(&'\n') {
-                    chars.next(); // skip '\n'
-                    if !buffer.is_empty() {
+                match chars.peek() {
+                    Some(&'\n') => {
+                        chars.next(); // skip '\n'
+                        flush_buffer(&mut tokens, &mut buffer, start);
+
                         tokens.push(Token {
-                            token_type: TokenType::Text,
-                            raw: buffer.clone(),
+                            token_type: TokenType::HardLineBreak,
+                            raw: "\\".to_string(),
+                            span: Span::new(start, chars.offset()),
                         });
-                        buffer.clear();
                     }
+                    Some(&c)
+                        if c.is_ascii_punctuation()
+                            || (options.ruby && matches!(c, '｜' | '《' | '》')) =>
+                    {
+                        chars.next(); // skip escaped character
+                        flush_buffer(&mut tokens, &mut buffer, start);
 
-                    tokens.push(Token {
-                        token_type: TokenType::HardLineBreak,
-                        raw: "\\".to_string(),
-                    });
-                } else {
-                    buffer.push('\\');
-                }
-            }
-            '\n' => {
-                if is_head_of_line(&tokens, buffer.clone()) {
-                    tokens.push(Token {
-                        token_type: TokenType::BlankLine,
-                        raw: "\n".to_string(),
-                    });
-                } else {
-                    if !buffer.is_empty() {
                         tokens.push(Token {
-                            token_type: TokenType::Text,
-                            raw: buffer.clone(),
+                            token_type: TokenType::EscapedChar,
+                            raw: format!("\\{}", c),
+                            span: Span::new(start, chars.offset()),
                         });
-                        buffer.clear();
                     }
-                    tokens.push(Token {
-                        token_type: TokenType::SoftLineBreak,
-                        raw: "\n".to_string(),
-                    });
+                    _ => buffer.push('\\'),
                 }
+            }
+            '\n' => {
+                flush_buffer(&mut tokens, &mut buffer, start);
+                tokens.push(Token {
+                    token_type: TokenType::SoftLineBreak,
+                    raw: "\n".to_string(),
+                    span: Span::new(start, start + 1),
+                });
                 chars.next();
             }
             _ => {
@@ -478,453 +318,221 @@ pub(crate) fn tokenize(input: &str) -> Vec<Token> {
             }
         }
     }
-    if !buffer.is_empty() {
-        tokens.push(Token {
-            token_type: TokenType::Text,
-            raw: buffer.clone(),
-        });
-        buffer.clear();
-    }
+    let end = chars.offset();
+    flush_buffer(&mut tokens, &mut buffer, end);
 
     tokens
 }
 
-fn is_head_of_line(tokens: &[Token], buffer: String) -> bool {
-    if !tokens.is_empty() {
-        let token_type = &tokens.last().unwrap().token_type;
-        match token_type {
-            TokenType::SoftLineBreak
-            | TokenType::HardLineBreak
-            | TokenType::BlockQuote
-            | TokenType::BlankLine
-            | TokenType::ThemanticBreak
-            | TokenType::BulletListItem => {}
-            _ => return false,
+/// Tokenizes the link reference definitions at the start of a paragraph.
+///
+/// Returns the definitions and the number of bytes they occupy.
+pub(crate) fn tokenize_link_reference_definitions(input: &str) -> (Vec<Token>, usize) {
+    let mut tokens = vec![];
+    let mut consumed = 0;
+
+    let mut chars = Cursor::new(input);
+    while chars.peek() == Some(&'[') {
+        let mut definition = vec![];
+        tokenize_link_reference_definition(&mut definition, &mut chars);
+        match definition.pop() {
+            Some(
+                token @ Token {
+                    token_type: TokenType::LinkReferenceDefinition(..),
+                    ..
+                },
+            ) => tokens.push(token),
+            _ => break,
         }
-    }
 
-    if buffer.chars().all(|c| c == ' ') {
-        return true;
+        // 定義の後ろの空白と改行を読み飛ばす
+        while chars.peek() == Some(&' ') {
+            chars.next();
+        }
+        chars.next();
+        consumed = chars.offset();
     }
 
-    false
+    (tokens, consumed)
+}
+
+/// Pushes the buffered text, which ends at `end`, as a `Text` token.
+fn flush_buffer(tokens: &mut Vec<Token>, buffer: &mut String, end: usize) {
+    if !buffer.is_empty() {
+        tokens.push(Token {
+            token_type: TokenType::Text,
+            raw: buffer.clone(),
+            span: Span::new(end - buffer.len(), end),
+        });
+        buffer.clear();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        span::Span,
         token::{DelimiterType, Token, TokenType},
         tokenizer::tokenize,
+        ParseOptions,
     };
 
-    #[test]
-    fn tokenize_heading() {
-        // 見出し
-        let input = "# Heading 1\n## Heading 2\n### Heading 3\n#### Heading 4\n##### Heading 5\n###### Heading 6";
-        let tokens = tokenize(input);
-        assert_eq!(
-            tokens,
-            vec![
-                Token {
-                    token_type: TokenType::ATXHeading(1.into()),
-                    raw: "#".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Heading 1".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ATXHeading(2.into()),
-                    raw: "##".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Heading 2".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ATXHeading(3.into()),
-                    raw: "###".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Heading 3".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ATXHeading(4.into()),
-                    raw: "####".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Heading 4".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ATXHeading(5.into()),
-                    raw: "#####".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Heading 5".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ATXHeading(6.into()),
-                    raw: "######".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Heading 6".to_string(),
-                },
-            ]
-        );
-    }
-
-    #[test]
-    fn tokenize_indent() {
-        // インデント
-        let input = " Hello, World!\n  Hello, World!\n   Hello, World!\n    Hello, World!\n     Hello, World!";
-        let tokens = tokenize(input);
-        assert_eq!(
-            tokens,
-            vec![
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "  Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "   Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::IndentedCodeBlock,
-                    raw: "    ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::IndentedCodeBlock,
-                    raw: "    ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Hello, World!".to_string(),
-                },
-            ]
-        );
-    }
-
-    #[test]
-    fn tokenize_themantic_break() {
-        // 水平線
-        let input = "---\n***\n___";
-        let tokens = tokenize(input);
-        assert_eq!(
-            tokens,
-            vec![
-                Token {
-                    token_type: TokenType::ThemanticBreak,
-                    raw: "---".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ThemanticBreak,
-                    raw: "***".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::ThemanticBreak,
-                    raw: "___".to_string(),
-                },
-            ]
-        );
-    }
-
-    #[test]
-    fn tokenize_list_item() {
-        // リスト
-        let input = "- Hello, World!\n* Hello, World!\n+ Hello, World!\n- [ ] Hello, World!\n- [x] Hello, World!\n1. Hello, World!";
-        let tokens = tokenize(input);
-        assert_eq!(
-            tokens,
-            vec![
-                Token {
-                    token_type: TokenType::BulletListItem,
-                    raw: "- ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::BulletListItem,
-                    raw: "* ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::BulletListItem,
-                    raw: "+ ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::CheckListItem(false),
-                    raw: "- [ ] ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::CheckListItem(true),
-                    raw: "- [x] ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::OrderedListItem,
-                    raw: "1. ".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
-                },
-            ]
-        );
-    }
-
     #[test]
     fn tokenize_emphasis() {
         // 強調
         let input = "***Hello, **World!*";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::Emphasis(DelimiterType::LeftFlanking),
                     raw: "***".to_string(),
+                    span: Span::new(0, 3),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "Hello, ".to_string(),
+                    span: Span::new(3, 10),
                 },
                 Token {
                     token_type: TokenType::Emphasis(DelimiterType::LeftFlanking),
                     raw: "**".to_string(),
+                    span: Span::new(10, 12),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "World!".to_string(),
+                    span: Span::new(12, 18),
                 },
                 Token {
                     token_type: TokenType::Emphasis(DelimiterType::RightFlanking),
                     raw: "*".to_string(),
+                    span: Span::new(18, 19),
                 },
             ]
         );
 
         let input = "aaa_bbb_ccc\naaa*bbb*ccc";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::Text,
                     raw: "aaa_bbb_ccc".to_string(),
+                    span: Span::new(0, 11),
                 },
                 Token {
                     token_type: TokenType::SoftLineBreak,
                     raw: "\n".to_string(),
+                    span: Span::new(11, 12),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "aaa".to_string(),
+                    span: Span::new(12, 15),
                 },
                 Token {
                     token_type: TokenType::Emphasis(DelimiterType::Both),
                     raw: "*".to_string(),
+                    span: Span::new(15, 16),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "bbb".to_string(),
+                    span: Span::new(16, 19),
                 },
                 Token {
                     token_type: TokenType::Emphasis(DelimiterType::Both),
                     raw: "*".to_string(),
+                    span: Span::new(19, 20),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "ccc".to_string(),
+                    span: Span::new(20, 23),
                 },
             ]
         );
     }
 
     #[test]
-    fn tokenize_fenced_code_block() {
-        // コードブロック
-        let input = "```\nHello, World!\n```\naaa```bbb```ccc";
-        let tokens = tokenize(input);
+    fn tokenize_code_span() {
+        // コードスパン
+        let input = "`Hello, World!`\n``Hello, World!``";
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
-                    token_type: TokenType::FencedCodeBlock,
-                    raw: "```".to_string(),
-                },
-                Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
+                    token_type: TokenType::CodeSpan,
+                    raw: "`".to_string(),
+                    span: Span::new(0, 1),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "Hello, World!".to_string(),
+                    span: Span::new(1, 14),
                 },
                 Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::FencedCodeBlock,
-                    raw: "```".to_string(),
+                    token_type: TokenType::CodeSpan,
+                    raw: "`".to_string(),
+                    span: Span::new(14, 15),
                 },
                 Token {
                     token_type: TokenType::SoftLineBreak,
                     raw: "\n".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "aaa".to_string(),
+                    span: Span::new(15, 16),
                 },
                 Token {
                     token_type: TokenType::CodeSpan,
-                    raw: "```".to_string(),
+                    raw: "``".to_string(),
+                    span: Span::new(16, 18),
                 },
                 Token {
                     token_type: TokenType::Text,
-                    raw: "bbb".to_string(),
+                    raw: "Hello, World!".to_string(),
+                    span: Span::new(18, 31),
                 },
                 Token {
                     token_type: TokenType::CodeSpan,
-                    raw: "```".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: "ccc".to_string(),
-                },
+                    raw: "``".to_string(),
+                    span: Span::new(31, 33),
+                }
             ]
         );
-    }
 
-    #[test]
-    fn tokenize_code_span() {
-        // コードスパン
-        let input = "`Hello, World!`\n``Hello, World!``";
-        let tokens = tokenize(input);
+        let input = "aaa```bbb```ccc";
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
-                Token {
-                    token_type: TokenType::CodeSpan,
-                    raw: "`".to_string(),
-                },
                 Token {
                     token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
+                    raw: "aaa".to_string(),
+                    span: Span::new(0, 3),
                 },
                 Token {
                     token_type: TokenType::CodeSpan,
-                    raw: "`".to_string(),
+                    raw: "```".to_string(),
+                    span: Span::new(3, 6),
                 },
                 Token {
-                    token_type: TokenType::SoftLineBreak,
-                    raw: "\n".to_string(),
+                    token_type: TokenType::Text,
+                    raw: "bbb".to_string(),
+                    span: Span::new(6, 9),
                 },
                 Token {
                     token_type: TokenType::CodeSpan,
-                    raw: "``".to_string(),
+                    raw: "```".to_string(),
+                    span: Span::new(9, 12),
                 },
                 Token {
                     token_type: TokenType::Text,
-                    raw: "Hello, World!".to_string(),
+                    raw: "ccc".to_string(),
+                    span: Span::new(12, 15),
                 },
-                Token {
-                    token_type: TokenType::CodeSpan,
-                    raw: "``".to_string(),
-                }
             ]
         );
     }
@@ -933,41 +541,48 @@ mod tests {
     fn tokenize_auto_link() {
         // 自動リンク
         let input = "<https://example.com>\n<mailto:example@example>";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::AutoLink,
                     raw: "<https://example.com>".to_string(),
+                    span: Span::new(0, 21),
                 },
                 Token {
                     token_type: TokenType::SoftLineBreak,
                     raw: "\n".to_string(),
+                    span: Span::new(21, 22),
                 },
                 Token {
                     token_type: TokenType::AutoLink,
                     raw: "<mailto:example@example>".to_string(),
+                    span: Span::new(22, 46),
                 },
             ]
         );
-    }
 
-    #[test]
-    fn tokenize_html_block() {
-        // HTMLブロック
-        let input = "<form>\nHello, World!\n</form>\n\n<blockquote>\nHello, World!\n</blockquote>";
-        let tokens = tokenize(input);
+        // 後ろに文字が続くメールアドレスと、`%` や非ASCII文字を含むURI
+        let input = "<foo@bar.example.com>!<http://例え.jp/%E3%81>";
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
-                    token_type: TokenType::HTMLBlock,
-                    raw: "<form>\nHello, World!\n</form>".to_string(),
+                    token_type: TokenType::AutoLink,
+                    raw: "<foo@bar.example.com>".to_string(),
+                    span: Span::new(0, 21),
+                },
+                Token {
+                    token_type: TokenType::Text,
+                    raw: "!".to_string(),
+                    span: Span::new(21, 22),
                 },
                 Token {
-                    token_type: TokenType::HTMLBlock,
-                    raw: "<blockquote>\nHello, World!\n</blockquote>".to_string(),
+                    token_type: TokenType::AutoLink,
+                    raw: "<http://例え.jp/%E3%81>".to_string(),
+                    span: Span::new(22, 47),
                 },
             ]
         );
@@ -977,25 +592,29 @@ mod tests {
     fn tokenize_inline_html() {
         // インラインHTML
         let input = "link: <a href=\"https://example.com\">Hello, World!</a>";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::Text,
                     raw: "link: ".to_string(),
+                    span: Span::new(0, 6),
                 },
                 Token {
                     token_type: TokenType::RawHTML,
                     raw: "<a href=\"https://example.com\">".to_string(),
+                    span: Span::new(6, 36),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "Hello, World!".to_string(),
+                    span: Span::new(36, 49),
                 },
                 Token {
                     token_type: TokenType::RawHTML,
                     raw: "</a>".to_string(),
+                    span: Span::new(49, 53),
                 },
             ]
         );
@@ -1005,33 +624,39 @@ mod tests {
     fn tokenize_link() {
         // リンク
         let input = "[Hello, World!](https://example.com)";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::LinkTextOpening,
                     raw: "[".to_string(),
+                    span: Span::new(0, 1),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "Hello, World!".to_string(),
+                    span: Span::new(1, 14),
                 },
                 Token {
                     token_type: TokenType::LinkTextClosing,
                     raw: "]".to_string(),
+                    span: Span::new(14, 15),
                 },
                 Token {
                     token_type: TokenType::LinkDestOpening,
                     raw: "(".to_string(),
+                    span: Span::new(15, 16),
                 },
                 Token {
                     token_type: TokenType::LinkDest,
                     raw: "https://example.com".to_string(),
+                    span: Span::new(16, 35),
                 },
                 Token {
                     token_type: TokenType::LinkDestClosing,
                     raw: ")".to_string(),
+                    span: Span::new(35, 36),
                 },
             ]
         );
@@ -1041,53 +666,39 @@ mod tests {
     fn tokenize_image() {
         // 画像
         let input = "![Hello, World!](https://example.com)";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::ImageTextOpening,
                     raw: "![".to_string(),
+                    span: Span::new(0, 2),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "Hello, World!".to_string(),
+                    span: Span::new(2, 15),
                 },
                 Token {
                     token_type: TokenType::LinkTextClosing,
                     raw: "]".to_string(),
+                    span: Span::new(15, 16),
                 },
                 Token {
                     token_type: TokenType::LinkDestOpening,
                     raw: "(".to_string(),
+                    span: Span::new(16, 17),
                 },
                 Token {
                     token_type: TokenType::LinkDest,
                     raw: "https://example.com".to_string(),
+                    span: Span::new(17, 36),
                 },
                 Token {
                     token_type: TokenType::LinkDestClosing,
                     raw: ")".to_string(),
-                },
-            ]
-        );
-    }
-
-    #[test]
-    fn test_block_quote() {
-        // 引用
-        let input = "> Hello, World!";
-        let tokens = tokenize(input);
-        assert_eq!(
-            tokens,
-            vec![
-                Token {
-                    token_type: TokenType::BlockQuote,
-                    raw: ">".to_string(),
-                },
-                Token {
-                    token_type: TokenType::Text,
-                    raw: " Hello, World!".to_string(),
+                    span: Span::new(36, 37),
                 },
             ]
         );
@@ -1097,59 +708,112 @@ mod tests {
     fn test_ruby() {
         // ルビ
         let input = "｜Hello, World!《こんにちは、世界！》";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::RubyTargetOpening,
                     raw: "｜".to_string(),
+                    span: Span::new(0, 3),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "Hello, World!".to_string(),
+                    span: Span::new(3, 16),
                 },
                 Token {
                     token_type: TokenType::RubyTextOpening,
                     raw: "《".to_string(),
+                    span: Span::new(16, 19),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "こんにちは、世界！".to_string(),
+                    span: Span::new(19, 46),
                 },
                 Token {
                     token_type: TokenType::RubyTextClosing,
                     raw: "》".to_string(),
+                    span: Span::new(46, 49),
                 },
             ]
         );
 
         let input = "こんにちは、世界《せかい》！";
-        let tokens = tokenize(input);
+        let tokens = tokenize(input, &ParseOptions::default());
         assert_eq!(
             tokens,
             vec![
                 Token {
                     token_type: TokenType::Text,
                     raw: "こんにちは、世界".to_string(),
+                    span: Span::new(0, 24),
                 },
                 Token {
                     token_type: TokenType::RubyTextOpening,
                     raw: "《".to_string(),
+                    span: Span::new(24, 27),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "せかい".to_string(),
+                    span: Span::new(27, 36),
                 },
                 Token {
                     token_type: TokenType::RubyTextClosing,
                     raw: "》".to_string(),
+                    span: Span::new(36, 39),
                 },
                 Token {
                     token_type: TokenType::Text,
                     raw: "！".to_string(),
+                    span: Span::new(39, 42),
                 },
             ]
         );
     }
+
+    #[test]
+    fn tokenize_backslash_escape() {
+        // バックスラッシュエスケープ
+        let input = "\\*a\\《\\a";
+        let tokens = tokenize(input, &ParseOptions::default());
+        assert_eq!(
+            tokens,
+            vec![
+                Token {
+                    token_type: TokenType::EscapedChar,
+                    raw: "\\*".to_string(),
+                    span: Span::new(0, 2),
+                },
+                Token {
+                    token_type: TokenType::Text,
+                    raw: "a".to_string(),
+                    span: Span::new(2, 3),
+                },
+                Token {
+                    token_type: TokenType::EscapedChar,
+                    raw: "\\《".to_string(),
+                    span: Span::new(3, 7),
+                },
+                Token {
+                    token_type: TokenType::Text,
+                    raw: "\\a".to_string(),
+                    span: Span::new(7, 9),
+                },
+            ]
+        );
+
+        // ルビが無効な場合は`《`をエスケープしない
+        let tokens = tokenize("\\《", &ParseOptions::strict());
+        assert_eq!(
+            tokens,
+            vec![Token {
+                token_type: TokenType::Text,
+                raw: "\\《".to_string(),
+                span: Span::new(0, 4),
+            }]
+        );
+    }
 }
@@ -0,0 +1,51 @@
+use crate::span::Span;
+use crate::token::{Token, TokenType};
+
+/// Tokenizes a code span at the start of `input`, opened by a backtick string of `count` backticks.
+///
+/// Returns `None` if no backtick string of the same length closes it.
+pub(super) fn tokenize_code_span(input: &str, count: usize, start: usize) -> Option<Vec<Token>> {
+    let mut offset = count;
+    while let Some(i) = input[offset..].find('`') {
+        let closing = offset + i;
+        let len = input[closing..].bytes().take_while(|&b| b == b'`').count();
+        if len == count {
+            return Some(vec![
+                Token {
+                    token_type: TokenType::CodeSpan,
+                    raw: input[..count].to_string(),
+                    span: Span::new(start, start + count),
+                },
+                Token {
+                    token_type: TokenType::Text,
+                    raw: input[count..closing].to_string(),
+                    span: Span::new(start + count, start + closing),
+                },
+                Token {
+                    token_type: TokenType::CodeSpan,
+                    raw: input[closing..closing + len].to_string(),
+                    span: Span::new(start + closing, start + closing + len),
+                },
+            ]);
+        }
+        offset = closing + len;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenize_code_span;
+
+    #[test]
+    fn test_code_span_tokenize() {
+        let tokens = tokenize_code_span("``a`b``c", 2, 1).unwrap();
+        assert_eq!(tokens[1].raw, "a`b");
+        assert_eq!(tokens[2].span.end, 8);
+
+        // 長さが一致しない
+        assert_eq!(tokenize_code_span("```a``", 3, 0), None);
+        assert_eq!(tokenize_code_span("`a``b", 1, 0), None);
+    }
+}
@@ -1,344 +1,195 @@
-use super::tokenize;
-use crate::token::{Token, TokenType};
+use super::Cursor;
+use crate::{
+    span::Span,
+    token::{Token, TokenType},
+};
 use once_cell::sync::Lazy;
 use regex::Regex;
-use std::{iter::Peekable, str::Chars};
 
 static LINK_LABEL_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^(([^\[\]\s]|\\\[|\\\])|([^\[\]]|\\\[|\\\]){1,999})").unwrap());
+/// Limit of nested parentheses in a link destination, as in the reference implementation.
+const MAX_LINK_DEST_PARENTHESES: usize = 32;
+
 static LINK_DEST_REGEX: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r"^(<([^<>]|\\\>|\\\<)+>|[^\s\x00-\x1F\x7F]+)").unwrap());
+    Lazy::new(|| Regex::new(r"^(<([^<>]|\\\>|\\\<)*>|[^\s\x00-\x1F\x7F]+)").unwrap());
 
-pub(super) fn tokenize_link_reference_definition(
-    tokens: &mut Vec<Token>,
-    chars: &mut Peekable<Chars>,
-) {
+pub(super) fn tokenize_link_reference_definition(tokens: &mut Vec<Token>, chars: &mut Cursor) {
+    let start = chars.offset();
     chars.next(); // skip '['
-    let link_label = match tokenize_link_label(chars) {
-        Ok(t) => t,
-        Err(ts) => {
-            tokens.extend(ts);
-
-            return;
-        }
-    };
-    chars.next(); // skip ']'
-
-    if chars.peek() != Some(&':') {
-        tokens.push(Token {
-            token_type: TokenType::LinkTextOpening,
-            raw: "[".to_string(),
-        });
-        tokens.extend(tokenize(&link_label));
-        tokens.push(Token {
-            token_type: TokenType::LinkTextClosing,
-            raw: "]".to_string(),
-        });
-
-        return;
-    }
-
-    chars.next(); // skip ':'
-
-    let mut is_newlined = false;
-    let mut spaces_between_label_and_dest = String::new();
-    while chars.peek() == Some(&' ') || !is_newlined && chars.peek() == Some(&'\n') {
-        let char = chars.next().unwrap();
-        if char == '\n' && !is_newlined {
-            is_newlined = true;
-        }
-        spaces_between_label_and_dest.push(char);
-    }
-    if is_newlined && chars.peek() == Some(&'\n') {
-        tokens.push(Token {
-            token_type: TokenType::LinkTextOpening,
-            raw: "[".to_string(),
-        });
-        tokens.extend(tokenize(&link_label));
-        tokens.push(Token {
-            token_type: TokenType::LinkTextClosing,
-            raw: "]".to_string(),
-        });
-        tokens.extend(tokenize(
-            format!(":{}", spaces_between_label_and_dest).as_str(),
-        ));
-
-        return;
-    }
-
-    let link_dest = match tokenize_link_dest(chars) {
-        Ok(t) => t,
-        Err(t) => {
+    let saved = chars.clone();
+
+    match tokenize_link_reference_definition_body(chars) {
+        Some((label, dest, title, raw)) => tokens.push(Token {
+            token_type: TokenType::LinkReferenceDefinition(label, dest, title),
+            raw,
+            span: Span::new(start, chars.offset()),
+        }),
+        None => {
+            *chars = saved;
             tokens.push(Token {
                 token_type: TokenType::LinkTextOpening,
                 raw: "[".to_string(),
+                span: Span::new(start, start + 1),
             });
-            tokens.extend(tokenize(&link_label));
-            tokens.push(Token {
-                token_type: TokenType::LinkTextClosing,
-                raw: "]".to_string(),
-            });
-            tokens.extend(tokenize(
-                format!(":{}{}", spaces_between_label_and_dest, t).as_str(),
-            ));
-
-            return;
         }
-    };
+    }
+}
 
-    let mut is_newlined = false;
-    let mut spaces_between_dest_and_title = String::new();
-    while chars.peek() == Some(&' ') || !is_newlined && chars.peek() == Some(&'\n') {
-        let char = chars.next().unwrap();
-        if char == '\n' && !is_newlined {
-            is_newlined = true;
-        }
-        spaces_between_dest_and_title.push(char);
+fn tokenize_link_reference_definition_body(
+    chars: &mut Cursor,
+) -> Option<(String, String, Option<String>, String)> {
+    let link_label = tokenize_link_label(chars)?;
+    // 空白だけのラベルは定義できない
+    if link_label.trim().is_empty() {
+        return None;
     }
-    if is_newlined && chars.peek() == Some(&'\n') {
-        tokens.push(Token {
-            token_type: TokenType::LinkTextOpening,
-            raw: "[".to_string(),
-        });
-        tokens.extend(tokenize(&link_label));
-        tokens.push(Token {
-            token_type: TokenType::LinkTextClosing,
-            raw: "]".to_string(),
-        });
-        tokens.extend(tokenize(
-            format!(
-                ":{}{}{}",
-                spaces_between_label_and_dest, link_dest, spaces_between_dest_and_title
-            )
-            .as_str(),
-        ));
-
-        return;
+    chars.next(); // skip ']'
+
+    if chars.peek() != Some(&':') {
+        return None;
     }
+    chars.next(); // skip ':'
 
-    let title = match chars.peek() {
-        Some(&'"') | Some(&'\'') | Some(&'(') => {
-            let quote = chars.next().unwrap(); // skip quote
+    let spaces_between_label_and_dest = skip_spaces(chars)?;
+    let link_dest = tokenize_link_dest(chars)?;
 
+    let after_dest = chars.clone();
+    let spaces_between_dest_and_title = skip_spaces(chars);
+    let title = match (&spaces_between_dest_and_title, chars.peek()) {
+        (Some(spaces), Some(&'"') | Some(&'\'') | Some(&'(')) if !spaces.is_empty() => {
+            let quote = chars.next().unwrap(); // skip quote
             let title = tokenize_link_title(chars, quote);
-            match title {
-                Ok(title) => {
-                    chars.next(); // skip quote
+            let closing = chars.next(); // skip quote
 
-                    Some(format!("{1}{}{1}", title, quote))
-                }
-                Err(t) => {
-                    tokens.push(Token {
-                        token_type: TokenType::LinkTextOpening,
-                        raw: "[".to_string(),
-                    });
-                    tokens.extend(tokenize(&link_label));
-                    tokens.push(Token {
-                        token_type: TokenType::LinkTextClosing,
-                        raw: "]".to_string(),
-                    });
-                    tokens.extend(tokenize(
-                        format!(
-                            ":{}{}{}{}{}",
-                            spaces_between_label_and_dest,
-                            link_dest,
-                            spaces_between_dest_and_title,
-                            quote,
-                            t
-                        )
-                        .as_str(),
-                    ));
-
-                    return;
-                }
-            }
+            title
+                .filter(|_| is_end_of_line(chars))
+                .map(|title| (quote, title, closing.unwrap()))
         }
         _ => None,
     };
-
-    tokens.push(Token {
-        token_type: TokenType::LinkReferenceDefinition(
-            link_label.clone(),
-            link_dest.clone(),
-            title
-                .clone()
-                .map(|title| title[1..title.len() - 1].to_string()),
-        ),
-        raw: format!(
-            "[{}]:{}{}{}{}",
-            link_label,
-            spaces_between_label_and_dest,
-            link_dest,
-            spaces_between_dest_and_title,
-            title.unwrap_or_default()
+    let (spaces_between_dest_and_title, title) = match title {
+        Some(title) => (
+            spaces_between_dest_and_title.unwrap_or_default(),
+            Some(title),
         ),
-    })
-}
-
-pub(super) fn tokenize_inline_link_dest(chars: &mut Peekable<Chars>) -> Result<Vec<Token>, String> {
-    let mut tokens = vec![];
-
-    let mut is_newlined = false;
-    let mut spaces_between_label_and_dest = String::new();
-    while chars.peek() == Some(&' ') || !is_newlined && chars.peek() == Some(&'\n') {
-        let char = chars.next().unwrap();
-        if char == '\n' && !is_newlined {
-            is_newlined = true;
-        }
-        spaces_between_label_and_dest.push(char);
-    }
-    if is_newlined && chars.peek() == Some(&'\n') {
-        return Err(format!("{}", spaces_between_label_and_dest));
-    }
+        None => {
+            // 行末で終わらないタイトルは定義に含めない
+            *chars = after_dest;
+            if !is_end_of_line(chars) {
+                return None;
+            }
 
-    let link_dest = match tokenize_link_dest(chars) {
-        Ok(dest) => dest,
-        Err(t) => {
-            return Err(format!("{}", t));
+            (String::new(), None)
         }
     };
 
-    let mut is_newlined = false;
-    let mut spaces_between_dest_and_title = String::new();
-    while chars.peek() == Some(&' ') || !is_newlined && chars.peek() == Some(&'\n') {
-        let char = chars.next().unwrap();
-        if char == '\n' && !is_newlined {
-            is_newlined = true;
-        }
-        spaces_between_dest_and_title.push(char);
-    }
-    if is_newlined && chars.peek() == Some(&'\n') {
-        return Err(format!("{}", spaces_between_dest_and_title));
-    }
+    let raw = format!(
+        "[{}]:{}{}{}{}",
+        link_label,
+        spaces_between_label_and_dest,
+        link_dest,
+        spaces_between_dest_and_title,
+        title
+            .as_ref()
+            .map(|(opening, title, closing)| format!("{}{}{}", opening, title, closing))
+            .unwrap_or_default()
+    );
+
+    Some((link_label, link_dest, title.map(|(_, title, _)| title), raw))
+}
+
+/// Consumes `(destination "title")` following a link text.
+pub(super) fn tokenize_inline_link_dest(chars: &mut Cursor) -> Option<Vec<Token>> {
+    let start = chars.offset();
+    chars.next(); // skip '('
 
+    skip_spaces(chars)?;
+    let dest_start = chars.offset();
+    let link_dest = if chars.peek() == Some(&')') {
+        String::new()
+    } else {
+        tokenize_link_dest(chars)?
+    };
+    let dest_end = chars.offset();
+
+    let spaces_between_dest_and_title = skip_spaces(chars)?;
     let title = match chars.peek() {
-        Some(&'"') | Some(&'\'') | Some(&'(') => {
+        Some(&'"') | Some(&'\'') | Some(&'(') if !spaces_between_dest_and_title.is_empty() => {
             let quote = chars.next().unwrap(); // skip quote
+            let title_start = chars.offset();
+            let title = tokenize_link_title(chars, quote)?;
+            let title_end = chars.offset();
+            chars.next(); // skip quote
 
-            let title = tokenize_link_title(chars, quote);
-            match title {
-                Ok(title) => {
-                    chars.next(); // skip quote
-
-                    Some(title)
-                }
-                Err(t) => {
-                    return Err(format!(
-                        "{}{}{}{}{}",
-                        spaces_between_label_and_dest,
-                        link_dest,
-                        spaces_between_dest_and_title,
-                        quote,
-                        t
-                    ))
-                }
-            }
+            Some((title, Span::new(title_start, title_end)))
         }
         _ => None,
     };
 
-    let mut is_newlined = false;
-    let mut spaces_between_title_and_close = String::new();
-    while chars.peek() == Some(&' ') || !is_newlined && chars.peek() == Some(&'\n') {
-        let char = chars.next().unwrap();
-        if char == '\n' && !is_newlined {
-            is_newlined = true;
-        }
-        spaces_between_title_and_close.push(char);
-    }
-    if is_newlined && chars.peek() == Some(&'\n') {
-        return Err(format!("{}", spaces_between_title_and_close));
+    skip_spaces(chars)?;
+    if chars.peek() != Some(&')') {
+        return None;
     }
-
-    if chars.peek() == Some(&')') {
-        chars.next(); // skip ')'
-        tokens.extend(vec![
-            Token {
-                token_type: TokenType::LinkDestOpening,
-                raw: "(".to_string(),
-            },
-            Token {
-                token_type: TokenType::LinkDest,
-                raw: link_dest,
-            },
-        ]);
-        if let Some(title) = title {
-            tokens.push(Token {
-                token_type: TokenType::LinkTitle,
-                raw: title,
-            });
-        }
+    let end = chars.offset();
+    chars.next(); // skip ')'
+
+    let mut tokens = vec![
+        Token {
+            token_type: TokenType::LinkDestOpening,
+            raw: "(".to_string(),
+            span: Span::new(start, start + 1),
+        },
+        Token {
+            token_type: TokenType::LinkDest,
+            raw: link_dest,
+            span: Span::new(dest_start, dest_end),
+        },
+    ];
+    if let Some((title, span)) = title {
         tokens.push(Token {
-            token_type: TokenType::LinkDestClosing,
-            raw: ")".to_string(),
+            token_type: TokenType::LinkTitle,
+            raw: title,
+            span,
         });
-    } else {
-        return Err(format!(
-            "{}{}{}{}{}",
-            spaces_between_label_and_dest,
-            link_dest,
-            spaces_between_dest_and_title,
-            title.unwrap_or_default(),
-            spaces_between_title_and_close
-        ));
     }
+    tokens.push(Token {
+        token_type: TokenType::LinkDestClosing,
+        raw: ")".to_string(),
+        span: Span::new(end, end + 1),
+    });
 
-    Ok(tokens)
+    Some(tokens)
 }
 
-pub(super) fn tokenize_link_label(chars: &mut Peekable<Chars>) -> Result<String, Vec<Token>> {
+/// Reads a link label up to, but not including, the closing `]`.
+pub(super) fn tokenize_link_label(chars: &mut Cursor) -> Option<String> {
     let mut link_label = String::new();
-    while chars.peek() != Some(&']') && chars.peek() != None {
-        let mut char = chars.next().unwrap();
-        if char == '\\' && chars.peek().is_some() {
-            link_label.push(char);
-            char = chars.next().unwrap();
-        } else if char == '\n' {
-            link_label.push(char);
-
-            if chars.peek() == Some(&'\n') {
-                let mut tokens = vec![];
-                tokens.push(Token {
-                    token_type: TokenType::LinkTextOpening,
-                    raw: "[".to_string(),
-                });
-                tokens.extend(tokenize(&link_label));
-
-                return Err(tokens);
-            }
-        } else if char == '[' {
-            let mut tokens = vec![];
-            tokens.push(Token {
-                token_type: TokenType::LinkTextOpening,
-                raw: "[".to_string(),
-            });
-            tokens.extend(tokenize(&link_label));
-            tokens.push(Token {
-                token_type: TokenType::LinkTextOpening,
-                raw: "[".to_string(),
-            });
+    while chars.peek() != Some(&']') {
+        let char = chars.next()?;
+        match char {
+            '\\' if chars.peek().is_some() => {
+                link_label.push(char);
+                link_label.push(chars.next().unwrap());
 
-            return Err(tokens);
+                continue;
+            }
+            '\n' if chars.peek() == Some(&'\n') => return None,
+            '[' => return None,
+            _ => {}
         }
 
         link_label.push(char);
     }
 
-    if !LINK_LABEL_REGEX.is_match(&link_label) || chars.peek() == None {
-        let mut tokens = vec![];
-        tokens.push(Token {
-            token_type: TokenType::LinkTextOpening,
-            raw: "[".to_string(),
-        });
-        tokens.extend(tokenize(&link_label));
-
-        return Err(tokens);
+    if !LINK_LABEL_REGEX.is_match(&link_label) {
+        return None;
     }
 
-    Ok(link_label)
+    Some(link_label)
 }
 
-pub(super) fn tokenize_link_dest(chars: &mut Peekable<Chars>) -> Result<String, String> {
+pub(super) fn tokenize_link_dest(chars: &mut Cursor) -> Option<String> {
     let allow_space = chars.peek() == Some(&'<');
     let mut link_dest = if allow_space {
         chars.next();
@@ -355,7 +206,7 @@ pub(super) fn tokenize_link_dest(chars: &mut Peekable<Chars>) -> Result<String,
 
                     break;
                 }
-                '\n' => return Err(link_dest),
+                '\n' => return None,
                 '\\' => {
                     link_dest.push(char);
                     chars.next();
@@ -369,6 +220,7 @@ pub(super) fn tokenize_link_dest(chars: &mut Peekable<Chars>) -> Result<String,
                 _ => link_dest.push(chars.next().unwrap()),
             },
             false => match char {
+                '(' if parentheses == MAX_LINK_DEST_PARENTHESES => return None,
                 '(' => {
                     parentheses += 1;
                     link_dest.push(chars.next().unwrap());
@@ -394,16 +246,15 @@ pub(super) fn tokenize_link_dest(chars: &mut Peekable<Chars>) -> Result<String,
         }
     }
     if !LINK_DEST_REGEX.is_match(&link_dest) {
-        return Err(link_dest);
+        return None;
     }
 
-    Ok(link_dest)
+    Some(link_dest)
 }
 
-pub(super) fn tokenize_link_title(
-    chars: &mut Peekable<Chars>,
-    quote: char,
-) -> Result<String, String> {
+/// Reads a link title up to, but not including, the closing quote.
+pub(super) fn tokenize_link_title(chars: &mut Cursor, quote: char) -> Option<String> {
+    let quote = if quote == '(' { ')' } else { quote };
     let mut link_title = String::new();
     while let Some(&char) = chars.peek() {
         match char {
@@ -422,32 +273,56 @@ pub(super) fn tokenize_link_title(
                 chars.next();
 
                 if chars.peek() == Some(&'\n') {
-                    return Err(link_title);
+                    return None;
                 }
             }
-            _ if char == quote => break,
+            _ if char == quote => return Some(link_title),
             _ => link_title.push(chars.next().unwrap()),
         }
     }
 
-    if link_title.is_empty() {
-        return Err(link_title);
+    None
+}
+
+/// Skips spaces and at most one line ending.
+fn skip_spaces(chars: &mut Cursor) -> Option<String> {
+    let mut spaces = String::new();
+    let mut is_newlined = false;
+    while chars.peek() == Some(&' ') || !is_newlined && chars.peek() == Some(&'\n') {
+        let char = chars.next().unwrap();
+        if char == '\n' {
+            is_newlined = true;
+        }
+        spaces.push(char);
+    }
+    if is_newlined && chars.peek() == Some(&'\n') {
+        return None;
+    }
+
+    Some(spaces)
+}
+
+fn is_end_of_line(chars: &mut Cursor) -> bool {
+    let mut chars = chars.clone();
+    while chars.peek() == Some(&' ') {
+        chars.next();
     }
 
-    Ok(link_title)
+    matches!(chars.peek(), Some(&'\n') | None)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
+        span::Span,
         token::{Token, TokenType},
-        tokenizer::link::tokenize_link_reference_definition,
+        tokenizer::{link::tokenize_link_reference_definition, Cursor},
     };
 
     #[test]
     fn test_tokenize_link_reference_definition() {
         let mut tokens = vec![];
-        let mut chars = "[link]: https://example.com".chars().peekable();
+        let mut chars = Cursor::new("[link]: https://example.com");
         tokenize_link_reference_definition(&mut tokens, &mut chars);
         assert_eq!(
             tokens,
@@ -458,11 +333,12 @@ mod tests {
                     None
                 ),
                 raw: "[link]: https://example.com".to_string(),
+                span: Span::new(0, 27),
             }]
         );
 
         let mut tokens = vec![];
-        let mut chars = "[link]: https://example.com \"title\"".chars().peekable();
+        let mut chars = Cursor::new("[link]: https://example.com \"title\"");
         tokenize_link_reference_definition(&mut tokens, &mut chars);
         assert_eq!(
             tokens,
@@ -473,6 +349,52 @@ mod tests {
                     Some("title".to_string())
                 ),
                 raw: "[link]: https://example.com \"title\"".to_string(),
+                span: Span::new(0, 35),
+            }]
+        );
+
+        // タイトルの後に文字が続くと定義にならない
+        let mut tokens = vec![];
+        let mut chars = Cursor::new("[link]: /url \"title\" ok");
+        tokenize_link_reference_definition(&mut tokens, &mut chars);
+        assert_eq!(
+            tokens,
+            vec![Token {
+                token_type: TokenType::LinkTextOpening,
+                raw: "[".to_string(),
+                span: Span::new(0, 1),
+            }]
+        );
+        assert_eq!(chars.offset(), 1);
+
+        // 次の行のタイトルが行末で終わらなければタイトルなしの定義になる
+        let mut tokens = vec![];
+        let mut chars = Cursor::new("[link]: /url\n\"title\" ok");
+        tokenize_link_reference_definition(&mut tokens, &mut chars);
+        assert_eq!(
+            tokens,
+            vec![Token {
+                token_type: TokenType::LinkReferenceDefinition(
+                    "link".to_string(),
+                    "/url".to_string(),
+                    None
+                ),
+                raw: "[link]: /url".to_string(),
+                span: Span::new(0, 12),
+            }]
+        );
+        assert_eq!(chars.offset(), 12);
+
+        // 空白だけのラベル
+        let mut tokens = vec![];
+        let mut chars = Cursor::new("[\n ]: /uri");
+        tokenize_link_reference_definition(&mut tokens, &mut chars);
+        assert_eq!(
+            tokens,
+            vec![Token {
+                token_type: TokenType::LinkTextOpening,
+                raw: "[".to_string(),
+                span: Span::new(0, 1),
             }]
         );
     }
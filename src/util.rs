@@ -1,5 +1,31 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use unicode_categories::UnicodeCategories;
 
+use crate::entity::decode_entity;
+
+/// Characters percent-encoded in link destinations, as by the reference implementation.
+const URI_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b';')
+    .remove(b'/')
+    .remove(b'?')
+    .remove(b':')
+    .remove(b'@')
+    .remove(b'&')
+    .remove(b'=')
+    .remove(b'+')
+    .remove(b'$')
+    .remove(b',')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'#');
+
 pub(super) fn is_unicode_punctuation(c: char) -> bool {
     c.is_ascii_punctuation()
         || c.is_punctuation()
@@ -10,4 +36,103 @@ pub(super) fn is_unicode_punctuation(c: char) -> bool {
         || c.is_punctuation_other()
         || c.is_punctuation_connector()
         || c.is_punctuation_dash()
+        || c.is_symbol()
+}
+
+/// Resolves backslash escapes and character references, as in link destinations and titles.
+pub(super) fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '\\' if rest[1..].starts_with(|c: char| c.is_ascii_punctuation()) => {
+                unescaped.push_str(&rest[1..2]);
+                rest = &rest[2..];
+
+                continue;
+            }
+            '&' => {
+                if let Some((characters, len)) = decode_entity(rest) {
+                    unescaped.push_str(&characters);
+                    rest = &rest[len..];
+
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        unescaped.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    unescaped
+}
+
+/// Resolves the raw destination of a link or a link reference definition.
+///
+/// Strips the `<>` around the destination, then resolves escapes and character references.
+pub(super) fn link_destination(raw: &str) -> String {
+    let destination = raw
+        .strip_prefix('<')
+        .and_then(|destination| destination.strip_suffix('>'))
+        .unwrap_or(raw);
+
+    unescape(destination)
+}
+
+/// Percent-encodes a link destination, keeping the `%` sequences already in it.
+pub(super) fn normalize_uri(uri: &str) -> String {
+    let mut normalized = String::with_capacity(uri.len());
+    let mut rest = uri;
+    while let Some(i) = rest.find('%') {
+        normalized.extend(utf8_percent_encode(&rest[..i], URI_ENCODE_SET));
+        match rest
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            Some(hex) => {
+                normalized.push('%');
+                normalized.push_str(hex);
+                rest = &rest[i + 3..];
+            }
+            None => {
+                normalized.push_str("%25");
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    normalized.extend(utf8_percent_encode(rest, URI_ENCODE_SET));
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{link_destination, normalize_uri, unescape};
+
+    #[test]
+    fn unescape_string() {
+        // バックスラッシュエスケープと文字参照
+        assert_eq!(unescape(r"/bar\* ti\*tle"), "/bar* ti*tle");
+        assert_eq!(unescape(r"\&ouml; &ouml; \a \"), "&ouml; ö \\a \\");
+    }
+
+    #[test]
+    fn link_destination_string() {
+        // リンク先
+        assert_eq!(link_destination(r"<b)c\>>"), "b)c>");
+        assert_eq!(link_destination("<>"), "");
+        assert_eq!(link_destination(r"/f&ouml;\(o\)"), "/fö(o)");
+    }
+
+    #[test]
+    fn normalize_uri_string() {
+        // パーセントエンコーディング
+        assert_eq!(
+            normalize_uri("/föö bar?q=[1]&r=%20%zz#x"),
+            "/f%C3%B6%C3%B6%20bar?q=%5B1%5D&r=%20%25zz#x"
+        );
+        assert_eq!(normalize_uri("mailto:a@b.c"), "mailto:a@b.c");
+    }
 }
@@ -0,0 +1,238 @@
+//! Runs the examples of the [CommonMark spec](https://spec.commonmark.org/0.31.2/) vendored in
+//! `tests/spec/spec.json` and reports the results per section.
+//!
+//! Run `cargo test --test spec -- --nocapture` to see the report.
+
+use std::panic;
+
+use caffe_paser::{parse_with_options, renderer::html::render, ParseOptions};
+use serde::Deserialize;
+
+/// Number of examples that must pass. Raise this when the parser gets more compliant.
+const MIN_PASSED: usize = 652;
+
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "pre",
+    "section",
+    "source",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+#[derive(Deserialize)]
+struct Example {
+    markdown: String,
+    html: String,
+    example: usize,
+    section: String,
+}
+
+struct Section {
+    name: String,
+    passed: usize,
+    failed: Vec<usize>,
+}
+
+#[test]
+fn commonmark_spec() {
+    let examples: Vec<Example> = serde_json::from_str(include_str!("spec/spec.json")).unwrap();
+
+    // パニックした例は失敗として数えるため、パニックメッセージを出力しない
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut sections: Vec<Section> = vec![];
+    for example in &examples {
+        let passed = panic::catch_unwind(|| {
+            render(&parse_with_options(
+                &example.markdown,
+                &ParseOptions::strict(),
+            ))
+        })
+        .map(|html| normalize_html(&html) == normalize_html(&example.html))
+        .unwrap_or(false);
+
+        if sections.last().map(|s| &s.name) != Some(&example.section) {
+            sections.push(Section {
+                name: example.section.clone(),
+                passed: 0,
+                failed: vec![],
+            });
+        }
+        let section = sections.last_mut().unwrap();
+        if passed {
+            section.passed += 1;
+        } else {
+            section.failed.push(example.example);
+        }
+    }
+
+    panic::set_hook(hook);
+
+    let passed = sections.iter().map(|s| s.passed).sum::<usize>();
+    for section in &sections {
+        println!(
+            "{:<42} {:>3}/{:<3} failed: {:?}",
+            section.name,
+            section.passed,
+            section.passed + section.failed.len(),
+            section.failed
+        );
+    }
+    println!("{:<42} {:>3}/{:<3}", "Total", passed, examples.len());
+
+    assert!(
+        passed >= MIN_PASSED,
+        "{} examples passed, expected at least {}",
+        passed,
+        MIN_PASSED
+    );
+}
+
+/// Normalizes insignificant differences in HTML: runs of whitespace are collapsed outside `<pre>`
+/// and whitespace next to block level tags is removed.
+fn normalize_html(html: &str) -> String {
+    enum Segment<'a> {
+        Tag { raw: &'a str, is_block: bool },
+        Text(String),
+    }
+
+    let mut segments = vec![];
+    let mut in_pre = false;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let raw = &rest[..tag_len(rest)];
+            let name = raw
+                .trim_start_matches(['<', '/'])
+                .split(|c: char| c.is_whitespace() || c == '>' || c == '/')
+                .next()
+                .unwrap_or_default()
+                .to_lowercase();
+            if name == "pre" {
+                in_pre = !raw.starts_with("</");
+            }
+            segments.push(Segment::Tag {
+                raw,
+                is_block: BLOCK_TAGS.contains(&name.as_str()),
+            });
+            rest = &rest[raw.len()..];
+        } else {
+            let text = &rest[..rest.find('<').unwrap_or(rest.len())];
+            segments.push(Segment::Text(if in_pre {
+                text.to_string()
+            } else {
+                collapse_whitespace(text)
+            }));
+            rest = &rest[text.len()..];
+        }
+    }
+
+    let is_block = |segment: Option<&Segment>| match segment {
+        Some(Segment::Tag { is_block, .. }) => *is_block,
+        Some(Segment::Text(_)) => false,
+        None => true,
+    };
+    let mut normalized = String::new();
+    for (i, segment) in segments.iter().enumerate() {
+        match segment {
+            Segment::Tag { raw, .. } => normalized.push_str(raw),
+            Segment::Text(text) => {
+                let mut text = text.as_str();
+                if is_block(i.checked_sub(1).and_then(|i| segments.get(i))) {
+                    text = text.trim_start_matches(' ');
+                }
+                if is_block(segments.get(i + 1)) {
+                    text = text.trim_end_matches(' ');
+                }
+                normalized.push_str(text);
+            }
+        }
+    }
+
+    normalized
+}
+
+/// Returns the length of the tag at the start of `html`, skipping `>` in quoted attribute values.
+fn tag_len(html: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '>') => return i + 1,
+            _ => {}
+        }
+    }
+
+    html.len()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    for c in text.chars() {
+        if !c.is_ascii_whitespace() {
+            collapsed.push(c);
+        } else if !collapsed.ends_with(' ') {
+            collapsed.push(' ');
+        }
+    }
+
+    collapsed
+}